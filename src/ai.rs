@@ -0,0 +1,216 @@
+use ggez::glam::*;
+
+use crate::{
+    projectile::Projectile, settings::Settings, terrain::Terrain, turn::PlayerId, Tank, ANGLE_STEP,
+    POWER_RANGE, POWER_STEP,
+};
+
+/// Most an easy opponent's angle is off from its estimate either way, in radians.
+const EASY_ANGLE_JITTER: f32 = 8.0 * ANGLE_STEP;
+
+/// Most an easy opponent's power is off from its estimate either way, as a
+/// fraction of the estimate.
+const EASY_POWER_JITTER: f32 = 0.15;
+
+/// Angle and power steps a hard opponent tries on its first, coarse pass. Each
+/// later pass searches around the best shot so far with steps a fifth the size.
+const HARD_COARSE_STEP: (f32, f32) = (5.0 * ANGLE_STEP, 25.0);
+
+/// How many times a hard opponent narrows its search.
+const HARD_PASSES: usize = 3;
+
+/// Longest a simulated shot is followed for before giving up on it, in seconds.
+const MAX_FLIGHT_TIME: f32 = 20.0;
+
+/// How fast a computer player turns its turret and changes its power while
+/// lining up a shot, per second.
+const AIM_SPEED: (f32, f32) = (40.0 * ANGLE_STEP, 40.0 * POWER_STEP);
+
+/// How good a computer player is at hitting its target.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Difficulty {
+    /// Eyeballs the distance as if the ground was flat and there was no wind,
+    /// then misses by a random amount.
+    Easy,
+    /// Works the shot out by trying them, wind, hills and all.
+    Hard,
+}
+
+impl Difficulty {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Hard => "hard",
+        }
+    }
+}
+
+/// The angle and power a shot is fired with.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Shot {
+    pub angle: f32,
+    pub power: f32,
+}
+
+/// Works out a shot for `shooter`'s tank at the nearest tank still standing.
+/// Returns `None` if there is nobody left to shoot at.
+pub fn plan_shot(
+    difficulty: Difficulty,
+    shooter: PlayerId,
+    tanks: &[Tank],
+    terrain: &Terrain,
+    wind: f32,
+    settings: &Settings,
+    rng: &mut oorandom::Rand32,
+) -> Option<Shot> {
+    let tank = &tanks[shooter.0];
+    let (target, _) = tanks
+        .iter()
+        .enumerate()
+        .filter(|&(index, other)| index != shooter.0 && other.health > 0)
+        .min_by(|(_, a), (_, b)| {
+            let (a, b) = (
+                a.center().distance(tank.center()),
+                b.center().distance(tank.center()),
+            );
+            a.total_cmp(&b)
+        })?;
+    let target = PlayerId(target);
+
+    let shot = match difficulty {
+        Difficulty::Easy => {
+            let estimate = estimate(tank, &tanks[target.0], settings.gravity);
+            Shot {
+                angle: estimate.angle + (rng.rand_float() * 2.0 - 1.0) * EASY_ANGLE_JITTER,
+                power: estimate.power * (1.0 + (rng.rand_float() * 2.0 - 1.0) * EASY_POWER_JITTER),
+            }
+        }
+        Difficulty::Hard => solve(shooter, target, tanks, terrain, wind, settings),
+    };
+
+    Some(Shot {
+        angle: shot.angle.clamp(0.0, std::f32::consts::PI),
+        power: shot.power.clamp(POWER_RANGE.0, POWER_RANGE.1),
+    })
+}
+
+/// A 45 degree shot with just the right power to land on `target` if the ground
+/// were flat and there was no wind.
+fn estimate(tank: &Tank, target: &Tank, gravity: f32) -> Shot {
+    let distance = target.center().x - tank.center().x;
+    let angle = if distance < 0.0 {
+        3.0 * std::f32::consts::FRAC_PI_4
+    } else {
+        std::f32::consts::FRAC_PI_4
+    };
+
+    // The range of a 45 degree shot on flat ground is v² / g.
+    Shot {
+        angle,
+        power: (distance.abs() * gravity).sqrt(),
+    }
+}
+
+/// Searches for the shot that lands closest to `target` by simulating them,
+/// first over the whole range of angles and powers and then around the best
+/// one found with finer and finer steps.
+fn solve(
+    shooter: PlayerId,
+    target: PlayerId,
+    tanks: &[Tank],
+    terrain: &Terrain,
+    wind: f32,
+    settings: &Settings,
+) -> Shot {
+    let tank = &tanks[shooter.0];
+    let mut best = (
+        estimate(tank, &tanks[target.0], settings.gravity),
+        f32::INFINITY,
+    );
+    let mut step = HARD_COARSE_STEP;
+    let mut range = ((0.0, std::f32::consts::PI), (POWER_RANGE.0, POWER_RANGE.1));
+
+    for _ in 0..HARD_PASSES {
+        let mut angle = range.0 .0;
+        while angle <= range.0 .1 {
+            let mut power = range.1 .0;
+            while power <= range.1 .1 {
+                let shot = Shot { angle, power };
+                let miss = simulate(shooter, target, shot, tanks, terrain, wind, settings);
+                if miss < best.1 {
+                    best = (shot, miss);
+                }
+                power += step.1;
+            }
+            angle += step.0;
+        }
+
+        // Look around the best shot so far more closely, without straying
+        // into shots the tank can't fire.
+        range = (
+            (
+                (best.0.angle - step.0).max(0.0),
+                (best.0.angle + step.0).min(std::f32::consts::PI),
+            ),
+            (
+                (best.0.power - step.1).max(POWER_RANGE.0),
+                (best.0.power + step.1).min(POWER_RANGE.1),
+            ),
+        );
+        step = (step.0 / 5.0, step.1 / 5.0);
+    }
+
+    best.0
+}
+
+/// Fires `shot` from `shooter`'s tank without anything actually happening, and
+/// returns how far away from `target`'s tank it landed. Shots that leave the
+/// screen or never come down count as infinitely far off.
+fn simulate(
+    shooter: PlayerId,
+    target: PlayerId,
+    shot: Shot,
+    tanks: &[Tank],
+    terrain: &Terrain,
+    wind: f32,
+    settings: &Settings,
+) -> f32 {
+    let mut tank = tanks[shooter.0].clone();
+    tank.angle = shot.angle;
+    tank.power = shot.power;
+    let mut projectile = Projectile::new(
+        tank.turret_tip(),
+        tank.launch_velocity(),
+        shooter,
+        tank.weapon,
+    );
+
+    // Step the shot exactly the way the real one would be.
+    let dt = 1.0 / settings.updates_per_second as f32;
+    for _ in 0..(MAX_FLIGHT_TIME / dt) as usize {
+        if let Some(impact) = projectile.update(dt, wind, settings.gravity, terrain, tanks) {
+            return match impact.tank {
+                Some(hit) if hit == target => 0.0,
+                _ => tanks[target.0].distance_to(impact.pos),
+            };
+        }
+        if projectile.is_off_screen(terrain.size()) {
+            break;
+        }
+    }
+
+    f32::INFINITY
+}
+
+/// Turns `tank`'s turret and changes its power towards `shot` for `dt`
+/// seconds, the way a player holding the aim keys would. Returns whether it is
+/// lined up yet.
+pub fn aim_towards(tank: &mut Tank, shot: Shot, dt: f32) -> bool {
+    let (angle, power) = (AIM_SPEED.0 * dt, AIM_SPEED.1 * dt);
+    tank.aim(
+        (shot.angle - tank.angle).clamp(-angle, angle),
+        (shot.power - tank.power).clamp(-power, power),
+    );
+
+    (shot.angle - tank.angle).abs() < 1e-3 && (shot.power - tank.power).abs() < 1e-3
+}
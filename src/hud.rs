@@ -0,0 +1,222 @@
+use ggez::{
+    glam::*,
+    graphics::{self, Canvas, Color},
+};
+
+use crate::{draw_text, turn::PlayerId, GameState, Phase, MAX_FUEL, MAX_HEALTH};
+
+/// Width and height of the bars drawn above each tank, in pixels.
+const TANK_BAR_SIZE: (f32, f32) = (20.0, 3.0);
+
+/// Gap between a tank's top and the lowest bar above it, in pixels.
+const TANK_BAR_GAP: f32 = 6.0;
+
+/// Width and height of the wind gauge, in pixels. The wind blows from the
+/// middle out to either end, reaching the end at the strongest wind there can be.
+const WIND_GAUGE_SIZE: (f32, f32) = (80.0, 4.0);
+
+/// Distance from the edges of the screen to the text along the top, in pixels.
+const MARGIN: f32 = 8.0;
+
+/// Draws the heads-up display over the game: scores and health along the top,
+/// the current player's aim, weapon and fuel during their turn, the wind, and
+/// bars above every tank. It only reads the game state, never changes it.
+pub fn draw(canvas: &mut Canvas, state: &GameState) {
+    // The menu has nothing to report yet.
+    if matches!(state.phase, Phase::MainMenu { .. }) {
+        return;
+    }
+
+    draw_scores(canvas, state);
+    draw_tank_bars(canvas, state);
+
+    let center = state.terrain.size().x / 2.0;
+    draw_text(
+        canvas,
+        &format!(
+            "Round {}/{}",
+            state.match_state.round(),
+            state.match_state.rounds
+        ),
+        16.0,
+        Vec2::new(center, MARGIN),
+        Vec2::new(0.5, 0.0),
+    );
+    if matches!(state.phase, Phase::Aiming | Phase::Firing) {
+        draw_text(
+            canvas,
+            &format!("{}'s turn", state.turns.current_player()),
+            16.0,
+            Vec2::new(center, MARGIN + 20.0),
+            Vec2::new(0.5, 0.0),
+        );
+    }
+
+    let arrow = if state.wind < 0.0 { "<" } else { ">" };
+    draw_text(
+        canvas,
+        &format!("Wind: {:.0} {}", state.wind.abs(), arrow),
+        16.0,
+        Vec2::new(center, MARGIN + 40.0),
+        Vec2::new(0.5, 0.0),
+    );
+    draw_wind_gauge(
+        canvas,
+        if state.settings.max_wind > 0.0 {
+            state.wind / state.settings.max_wind
+        } else {
+            0.0
+        },
+        Vec2::new(center, MARGIN + 62.0),
+    );
+
+    // Between turns the overlay takes the middle of the screen, and there's
+    // nobody aiming to report on.
+    if !matches!(state.phase, Phase::Aiming | Phase::Firing) {
+        return;
+    }
+
+    let tank = state.turns.tank(state.turns.current_player());
+    draw_text(
+        canvas,
+        &format!(
+            "Angle: {:.0}°  Power: {:.0}",
+            tank.angle.to_degrees(),
+            tank.power
+        ),
+        16.0,
+        Vec2::new(center, MARGIN + 70.0),
+        Vec2::new(0.5, 0.0),
+    );
+    let ammo = match tank.ammo(tank.weapon) {
+        Some(ammo) => ammo.to_string(),
+        None => "unlimited".to_string(),
+    };
+    draw_text(
+        canvas,
+        &format!("Weapon: {} ({})", tank.weapon.name(), ammo),
+        16.0,
+        Vec2::new(center, MARGIN + 90.0),
+        Vec2::new(0.5, 0.0),
+    );
+    draw_text(
+        canvas,
+        &format!("Fuel: {:.0}", tank.fuel),
+        16.0,
+        Vec2::new(center, MARGIN + 110.0),
+        Vec2::new(0.5, 0.0),
+    );
+}
+
+/// Health and score for every player. Players alternate between the left and
+/// right edges, two lines each.
+fn draw_scores(canvas: &mut Canvas, state: &GameState) {
+    for (index, tank) in state.turns.tanks().iter().enumerate() {
+        let player = PlayerId(index);
+        let (x, offset) = if index % 2 == 0 {
+            (MARGIN, 0.0)
+        } else {
+            (state.terrain.size().x - MARGIN, 1.0)
+        };
+        let y = MARGIN + (index / 2) as f32 * 40.0;
+        draw_text(
+            canvas,
+            &format!("{}: {} HP", player, tank.health),
+            16.0,
+            Vec2::new(x, y),
+            Vec2::new(offset, 0.0),
+        );
+        draw_text(
+            canvas,
+            &format!("Score: {}", state.match_state.score(player, tank)),
+            16.0,
+            Vec2::new(x, y + 20.0),
+            Vec2::new(offset, 0.0),
+        );
+    }
+}
+
+/// A health bar above every tank still standing, with a fuel bar above that
+/// for the tank being driven.
+fn draw_tank_bars(canvas: &mut Canvas, state: &GameState) {
+    for (index, tank) in state.turns.tanks().iter().enumerate() {
+        if tank.health <= 0 {
+            continue;
+        }
+
+        let top = tank.body().top() - TANK_BAR_GAP - TANK_BAR_SIZE.1;
+        let bar = graphics::Rect::new(
+            tank.pos.x - TANK_BAR_SIZE.0 / 2.0,
+            top,
+            TANK_BAR_SIZE.0,
+            TANK_BAR_SIZE.1,
+        );
+        draw_bar(
+            canvas,
+            bar,
+            tank.health as f32 / MAX_HEALTH as f32,
+            Color::from_rgb(200, 40, 40),
+        );
+
+        let driving =
+            state.phase == Phase::Aiming && state.turns.current_player() == PlayerId(index);
+        if driving {
+            let mut bar = bar;
+            bar.y -= TANK_BAR_SIZE.1 + 1.0;
+            draw_bar(
+                canvas,
+                bar,
+                tank.fuel / MAX_FUEL,
+                Color::from_rgb(220, 180, 0),
+            );
+        }
+    }
+}
+
+/// A bar across `rect`, filled from the left by the fraction `fill`.
+fn draw_bar(canvas: &mut Canvas, rect: graphics::Rect, fill: f32, color: Color) {
+    canvas.draw(
+        &graphics::Quad,
+        graphics::DrawParam::new()
+            .dest_rect(rect)
+            .color(Color::from_rgb(60, 60, 60)),
+    );
+
+    let mut filled = rect;
+    filled.w *= fill.clamp(0.0, 1.0);
+    canvas.draw(
+        &graphics::Quad,
+        graphics::DrawParam::new().dest_rect(filled).color(color),
+    );
+}
+
+/// A gauge centered on `center` that fills out from the middle in the direction
+/// the wind blows, further the stronger it is. `wind` goes from -1 for the
+/// strongest wind to the left to 1 for the strongest to the right.
+fn draw_wind_gauge(canvas: &mut Canvas, wind: f32, center: Vec2) {
+    let (width, height) = WIND_GAUGE_SIZE;
+    canvas.draw(
+        &graphics::Quad,
+        graphics::DrawParam::new()
+            .dest_rect(graphics::Rect::new(
+                center.x - width / 2.0,
+                center.y - height / 2.0,
+                width,
+                height,
+            ))
+            .color(Color::from_rgb(200, 200, 200)),
+    );
+
+    let length = wind.clamp(-1.0, 1.0) * width / 2.0;
+    canvas.draw(
+        &graphics::Quad,
+        graphics::DrawParam::new()
+            .dest_rect(graphics::Rect::new(
+                center.x + length.min(0.0),
+                center.y - height / 2.0,
+                length.abs(),
+                height,
+            ))
+            .color(Color::from_rgb(70, 130, 200)),
+    );
+}
@@ -1,3 +1,17 @@
+mod ai;
+mod hud;
+mod particles;
+mod projectile;
+mod replay;
+mod scoring;
+mod settings;
+mod terrain;
+mod turn;
+mod weapons;
+
+use std::path::{Path, PathBuf};
+
+use ai::Shot;
 use ggez::{
     event,
     glam::*,
@@ -5,107 +19,676 @@ use ggez::{
     input::keyboard::{KeyCode, KeyInput},
     Context, GameResult,
 };
+use particles::Particles;
+use projectile::{Impact, Projectile};
+use replay::{MatchReplay, Playback, ShotReplay};
+use scoring::MatchState;
+use settings::{Action, Settings};
+use terrain::Terrain;
+use turn::{PlayerController, PlayerId, TurnManager};
+use weapons::Weapon;
 
+/// Window size used unless the settings pick another one, in pixels.
 const SCREEN_SIZE: (usize, usize) = (480, 480);
 
-#[derive(Eq, PartialEq, Debug)]
-struct Tank {
-    pos: Position,
-    next_pos: Option<Position>,
-    last_known_position: Option<Position>,
-    direction: Direction,
-    last_known_direction: Option<Direction>,
-    next_direction: Option<Direction>,
-}
+/// Health every tank starts each round with.
+const MAX_HEALTH: i32 = 100;
 
-#[derive(Eq, PartialEq, Debug, Clone, Copy)]
-struct Position {
-    x: isize,
-    y: isize,
+/// Fewest and most players a match can be started with.
+const PLAYER_RANGE: (usize, usize) = (2, 4);
+
+/// Side length of one grid cell, in pixels.
+const GRID_CELL_SIZE: isize = 10;
+
+/// How many window pixels each pixel of the low-resolution canvas covers when
+/// retro rendering is turned on.
+const PIXEL_SCALE: usize = 4;
+
+/// How long the next player's name is shown before their turn starts, in
+/// seconds.
+const TURN_TRANSITION_TIME: f32 = 1.0;
+
+/// Launch speed a tank starts with, in pixels per second.
+const DEFAULT_POWER: f32 = 300.0;
+
+/// Slowest and fastest a shot can be launched, in pixels per second.
+const POWER_RANGE: (f32, f32) = (50.0, 500.0);
+
+/// How much one press of Up or Down changes the power.
+const POWER_STEP: f32 = 5.0;
+
+/// How much one press of PageUp or PageDown changes the power.
+const POWER_PAGE_STEP: f32 = 50.0;
+
+/// How much one press of an aim key turns the turret: one degree, in radians.
+const ANGLE_STEP: f32 = std::f32::consts::PI / 180.0;
+
+/// Length and thickness of the turret barrel, in pixels.
+const TURRET_SIZE: (f32, f32) = (10.0, 3.0);
+
+/// Width and height of a tank's body, in pixels.
+const TANK_SIZE: (f32, f32) = (GRID_CELL_SIZE as f32, GRID_CELL_SIZE as f32);
+
+/// How far a tank can drive in one turn, in pixels.
+const MAX_FUEL: f32 = 60.0;
+
+/// Steepest slope a tank can drive up, as height gained per pixel driven.
+const MAX_CLIMB: f32 = 1.5;
+
+#[derive(PartialEq, Debug, Clone)]
+struct Tank {
+    /// Center of the tank's body, in pixels.
+    pos: Vec2,
+    /// How far the body is tilted to match the slope of the ground under it,
+    /// in radians clockwise.
+    tilt: f32,
+    color: Color,
+    health: i32,
+    /// Points earned this round, one for every point of damage dealt to the
+    /// other tanks.
+    score: i32,
+    /// Turret angle in radians, measured counter-clockwise from pointing right.
+    angle: f32,
+    /// Launch speed of the next shot, in pixels per second.
+    power: f32,
+    /// What the tank fires next.
+    weapon: Weapon,
+    /// Shots left of each weapon in `Weapon::ARSENAL`, in the same order.
+    /// `None` means there's no limit.
+    ammo: [Option<u32>; Weapon::ARSENAL.len()],
+    /// The direction the tank is being driven in, if any. The tank keeps
+    /// rolling this way until it is let go or runs out of fuel.
+    direction: Option<Direction>,
+    /// How much further the tank can drive this turn, in pixels.
+    fuel: f32,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone, Copy)]
 enum Direction {
-    Up,
-    Down,
     Left,
     Right,
 }
 
-impl Direction {
-    /// We create a helper function that will allow us to easily get the inverse
-    /// of a `Direction` which we can use later to check if the player should be
-    /// able to move the snake in a certain direction.
-    pub fn inverse(&self) -> Self {
-        match *self {
-            Direction::Up => Direction::Down,
-            Direction::Down => Direction::Up,
-            Direction::Left => Direction::Right,
-            Direction::Right => Direction::Left,
-        }
-    }
+/// Everything drawn in the game belongs to one of these layers. Layers are drawn
+/// in `Layer::DRAW_ORDER`, so nothing has to care about the order it happens to
+/// be drawn in within `draw`.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+enum Layer {
+    Terrain,
+    Tanks,
+    Projectiles,
+    /// Explosions, smoke and flying dirt.
+    Effects,
+    /// Health readouts, aim and whose turn it is. Always drawn over the world,
+    /// unless turned off with F1.
+    Hud,
+    /// Prompts and messages that sit above everything else, HUD included.
+    Overlay,
+}
+
+impl Layer {
+    /// All layers from the bottom up. Later layers are drawn over earlier ones.
+    pub const DRAW_ORDER: [Layer; 6] = [
+        Layer::Terrain,
+        Layer::Tanks,
+        Layer::Projectiles,
+        Layer::Effects,
+        Layer::Hud,
+        Layer::Overlay,
+    ];
 
-    /// We also create a helper function that will let us convert between a
-    /// `ggez` `Keycode` and the `Direction` that it represents. Of course,
-    /// not every keycode represents a direction, so we return `None` if this
-    /// is the case.
-    pub fn from_keycode(key: KeyCode) -> Option<Direction> {
-        match key {
-            KeyCode::Up => Some(Direction::Up),
-            KeyCode::Down => Some(Direction::Down),
-            KeyCode::Left => Some(Direction::Left),
-            KeyCode::Right => Some(Direction::Right),
-            _ => None,
+    /// Calls `draw` once for every layer, in `DRAW_ORDER`.
+    fn draw_in_order(mut draw: impl FnMut(Layer)) {
+        for layer in Layer::DRAW_ORDER {
+            draw(layer);
         }
     }
 }
 
-impl Position {
-    pub fn new(x: isize, y: isize) -> Self {
-        Position { x, y }
+/// The pause before a turn starts, counted in updates at the configured rate.
+fn turn_transition(settings: &Settings) -> Phase {
+    Phase::TurnTransition {
+        ticks: (TURN_TRANSITION_TIME * settings.updates_per_second as f32).round() as u32,
     }
+}
 
-    pub fn new_move(pos: Position, direction: Direction) -> Self {
-        match direction {
-            Direction::Up => Position::new(pos.x, pos.y - 1),
-            Direction::Down => Position::new(pos.x - 1, pos.y),
-            Direction::Left => Position::new(pos.x - 1, pos.y - 1),
-            Direction::Right => Position::new(pos.x + 1, pos.y + 1),
-        }
-    }
+/// The pixel of the low-resolution canvas that shows up at the window pixel
+/// `pos` when every canvas pixel is blown up to `scale` by `scale` window pixels.
+fn window_to_canvas(pos: Vec2, scale: usize) -> Vec2 {
+    (pos / scale as f32).floor()
+}
+
+/// Picks a seed for a brand new match.
+fn random_seed() -> u64 {
+    let mut seed = [0; 8];
+    getrandom::getrandom(&mut seed).expect("Could not create RNG seed");
+    u64::from_ne_bytes(seed)
+}
+
+/// The size of the battlefield in grid columns and rows: as many as fit in the
+/// window, so a bigger window gets a bigger battlefield rather than a
+/// stretched one.
+fn grid_size(settings: &Settings) -> (isize, isize) {
+    (
+        (settings.window.width / GRID_CELL_SIZE as f32) as isize,
+        (settings.window.height / GRID_CELL_SIZE as f32) as isize,
+    )
 }
 
-/// We implement the `From` trait, which in this case allows us to convert easily between
-/// a GridPosition and a ggez `graphics::Rect` which fills that grid cell.
-/// Now we can just call `.into()` on a `GridPosition` where we want a
-/// `Rect` that represents that grid cell.
-impl From<Position> for graphics::Rect {
-    fn from(pos: Position) -> Self {
-        graphics::Rect::new_i32((pos.x * 10) as i32, (pos.y * 10) as i32, 10, 10)
+/// Picks a new wind for the next turn, blowing anywhere from `max_wind` to the
+/// left to `max_wind` to the right.
+fn random_wind(rng: &mut oorandom::Rand32, max_wind: f32) -> f32 {
+    (rng.rand_float() * 2.0 - 1.0) * max_wind
+}
+
+/// Draws black `text` at `dest`. `offset` picks which point of the text ends up
+/// at `dest`, from (0, 0) for the top left to (1, 1) for the bottom right.
+fn draw_text(canvas: &mut Canvas, text: &str, scale: f32, dest: Vec2, offset: Vec2) {
+    let mut text = graphics::Text::new(text);
+    text.set_scale(scale);
+    canvas.draw(
+        &text,
+        graphics::DrawParam::new()
+            .dest(dest)
+            .offset(offset)
+            .color(Color::BLACK),
+    );
+}
+
+/// What the game is doing right now. Updating, drawing and input handling all
+/// go by the phase, so each one only has to deal with what can happen in it.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+enum Phase {
+    /// The start menu, where the number of players for the next match is
+    /// picked, along with who plays everyone but the first player.
+    MainMenu {
+        players: usize,
+        opponents: PlayerController,
+    },
+    /// The current player can move and aim, and has not fired yet.
+    Aiming,
+    /// The current player's shot is in the air. Nobody can act until it lands.
+    Firing,
+    /// A short pause announcing whose turn is next, lasting `ticks` more updates.
+    TurnTransition { ticks: u32 },
+    /// `winner` took the round, and the match has more rounds to go.
+    RoundOver { winner: PlayerId },
+    /// Every round has been played.
+    GameOver,
+}
+
+/// How a match starts out. It all comes from the seed, so restarting a match
+/// from its seed puts everything back exactly the way it was.
+#[derive(PartialEq, Debug)]
+struct MatchSetup {
+    /// Carries on driving everything random once the match is underway.
+    rng: oorandom::Rand32,
+    terrain: Terrain,
+    tanks: Vec<Tank>,
+    wind: f32,
+}
+
+impl MatchSetup {
+    fn new(seed: u64, players: usize, settings: &Settings) -> Self {
+        let mut rng = oorandom::Rand32::new(seed);
+        let terrain = Terrain::generate(&mut rng, grid_size(settings));
+        let wind = random_wind(&mut rng, settings.max_wind);
+        let tanks = spawn_tanks(&terrain, players);
+
+        MatchSetup {
+            rng,
+            terrain,
+            tanks,
+            wind,
+        }
     }
 }
 
 struct GameState {
-    tank: Tank,
-    //    terrain: graphics::Mesh,
+    /// Seed the match was generated from, kept so it can be restarted as-is.
+    seed: u64,
+    /// Drives everything random that happens during the match.
+    rng: oorandom::Rand32,
+    phase: Phase,
+    turns: TurnManager,
+    /// The shot a computer player is lining up this turn.
+    ai_shot: Option<Shot>,
+    /// Round count and the points banked by each player so far.
+    match_state: MatchState,
+    projectiles: Vec<Projectile>,
+    particles: Particles,
+    /// Horizontal acceleration applied to projectiles this turn, in pixels per
+    /// second squared. Positive values blow to the right.
+    wind: f32,
+    terrain: Terrain,
+    /// The terrain is built into a mesh so it isn't rebuilt every frame. It only
+    /// gets rebuilt when the terrain changes.
+    terrain_mesh: graphics::Mesh,
+    /// Whether the world is drawn to a small offscreen canvas and upscaled
+    /// with nearest-neighbor filtering for a retro pixel-art look.
+    pixelated: bool,
+    /// Offscreen target for pixelated rendering, created the first time it is needed.
+    pixel_canvas: Option<graphics::Image>,
+    /// Set while the player is being asked whether to throw away the current match.
+    confirm_restart: bool,
+    /// Whether the heads-up display is drawn.
+    show_hud: bool,
+    /// Every shot fired this match, for instant replays and saving to disk.
+    replay: MatchReplay,
+    /// The shot in the air right now, recorded as it happens.
+    recording: Option<ShotReplay>,
+    /// Set while a replay is playing. The game is paused underneath it.
+    playback: Option<Playback>,
+    /// Where the match replay was last saved to, if it has been.
+    saved_replay: Option<PathBuf>,
+    settings: Settings,
 }
 
 impl GameState {
-    fn new() -> Self {
-        let pos = Position::new(10, 10);
-        let tank = Tank::new(pos, Direction::Right);
+    /// Opens the start menu, with a battlefield from a random seed behind it.
+    fn new(ctx: &mut Context, settings: Settings) -> GameResult<Self> {
+        let controllers = vec![PlayerController::Human; PLAYER_RANGE.0];
+        let mut state = GameState::from_seed(ctx, random_seed(), controllers, settings)?;
+        state.phase = Phase::MainMenu {
+            players: PLAYER_RANGE.0,
+            opponents: PlayerController::Human,
+        };
 
-        /* let terrain = &mut graphics::MeshBuilder::new();
+        Ok(state)
+    }
 
-        terrain
-        .rectangle(
-        graphics::DrawMode::stroke(1.0),
-        graphics::Rect::new(200.0, 200.0, 50.0, 50.0),
-        graphics::Color::new(1.0, 0.0, 0.0, 1.0),
-        )
-        .expect("Create mesh failed!"); */
+    /// Sets up a fresh match with one player for each of `controllers`. The
+    /// same seed and player count always produce the same battlefield.
+    fn from_seed(
+        ctx: &mut Context,
+        seed: u64,
+        controllers: Vec<PlayerController>,
+        settings: Settings,
+    ) -> GameResult<Self> {
+        let players = controllers.len();
+        let MatchSetup {
+            rng,
+            terrain,
+            tanks,
+            wind,
+        } = MatchSetup::new(seed, players, &settings);
+        let terrain_mesh = terrain.build_mesh(ctx)?;
+
+        Ok(GameState {
+            seed,
+            rng,
+            phase: turn_transition(&settings),
+            turns: TurnManager::new(tanks, controllers),
+            ai_shot: None,
+            match_state: MatchState::new(settings.rounds, players),
+            projectiles: Vec::new(),
+            particles: Particles::new(ctx, seed)?,
+            wind,
+            terrain,
+            terrain_mesh,
+            pixelated: false,
+            pixel_canvas: None,
+            confirm_restart: false,
+            show_hud: true,
+            replay: MatchReplay::new(seed, settings.updates_per_second),
+            recording: None,
+            playback: None,
+            saved_replay: None,
+            settings,
+        })
+    }
+
+    /// Swaps in `state`, keeping the display settings.
+    fn replace(&mut self, mut state: GameState) {
+        state.pixelated = self.pixelated;
+        state.show_hud = self.show_hud;
+        state.pixel_canvas = self.pixel_canvas.take();
+        *self = state;
+    }
+
+    /// Starts the match over from the same seed with the same players.
+    fn restart(&mut self, ctx: &mut Context) -> GameResult {
+        let controllers = self.turns.controllers().to_vec();
+        let state = GameState::from_seed(ctx, self.seed, controllers, self.settings.clone())?;
+        self.replace(state);
+
+        Ok(())
+    }
+
+    /// Moves on to the next round of the match on freshly generated terrain.
+    fn start_round(&mut self, ctx: &mut Context) -> GameResult {
+        self.terrain = Terrain::generate(&mut self.rng, grid_size(&self.settings));
+        self.terrain_mesh = self.terrain.build_mesh(ctx)?;
+        let players = self.turns.tanks().len();
+        self.turns.new_round(spawn_tanks(&self.terrain, players));
+        self.projectiles.clear();
+        self.wind = random_wind(&mut self.rng, self.settings.max_wind);
+        self.phase = turn_transition(&self.settings);
+
+        Ok(())
+    }
+
+    /// Plays back every shot of `replay` over the game, which carries on once
+    /// it's done.
+    fn watch(&mut self, ctx: &mut Context, replay: &MatchReplay) -> GameResult {
+        self.playback = Playback::new(
+            ctx,
+            &replay.shots,
+            replay.updates_per_second,
+            self.settings.updates_per_second,
+        )?;
+
+        Ok(())
+    }
+
+    /// Shows the last shot of the match again.
+    fn instant_replay(&mut self, ctx: &mut Context) -> GameResult {
+        let last = self.replay.shots.len().saturating_sub(1);
+        self.playback = Playback::new(
+            ctx,
+            &self.replay.shots[last..],
+            self.replay.updates_per_second,
+            self.settings.updates_per_second,
+        )?;
+
+        Ok(())
+    }
+
+    /// Saves every shot of the match so far to the replays directory.
+    fn save_replay(&mut self) {
+        match self.replay.save() {
+            Ok(path) => {
+                println!("Saved replay to {}", path.display());
+                self.saved_replay = Some(path);
+            }
+            Err(err) => println!("Could not save replay: {}", err),
+        }
+    }
+
+    /// Draws everything in the game world onto `canvas` in window coordinates.
+    fn draw_world(&self, canvas: &mut Canvas) {
+        Layer::draw_in_order(|layer| self.draw_layer(canvas, layer));
+    }
+
+    fn draw_layer(&self, canvas: &mut Canvas, layer: Layer) {
+        // A replay takes over the whole screen, apart from the explosions.
+        if let Some(playback) = &self.playback {
+            match layer {
+                Layer::Effects => self.particles.draw(canvas),
+                Layer::Hud if !self.show_hud => {}
+                _ => playback.draw_layer(canvas, layer),
+            }
+            return;
+        }
+
+        match layer {
+            Layer::Terrain => canvas.draw(&self.terrain_mesh, graphics::DrawParam::new()),
+            Layer::Tanks => {
+                for tank in self.turns.tanks() {
+                    tank.draw(canvas);
+                }
+            }
+            Layer::Projectiles => {
+                for projectile in &self.projectiles {
+                    projectile.draw(canvas);
+                }
+            }
+            Layer::Effects => self.particles.draw(canvas),
+            Layer::Hud if self.show_hud => hud::draw(canvas, self),
+            Layer::Hud => {}
+            Layer::Overlay => {
+                let center = self.terrain.size() * Vec2::new(0.5, 0.25);
+                let (heading, hint) = if self.confirm_restart {
+                    ("Restart match? (Y/N)".to_string(), None)
+                } else {
+                    match self.phase {
+                        Phase::MainMenu { players, opponents } => (
+                            format!("Players: < {} >", players),
+                            Some(format!(
+                                "Opponents: {}\n\nLeft/Right: players  Up/Down: opponents\nEnter: start",
+                                opponents.name()
+                            )),
+                        ),
+                        Phase::Aiming | Phase::Firing => return,
+                        Phase::TurnTransition { .. } => {
+                            (format!("{}'s turn", self.turns.current_player()), None)
+                        }
+                        Phase::RoundOver { winner } => (
+                            format!("{} wins round {}!", winner, self.match_state.rounds_played),
+                            Some(format!(
+                                "Press Enter for the next round\n{}",
+                                self.replay_hint()
+                            )),
+                        ),
+                        Phase::GameOver => {
+                            let heading = match self.match_state.leader() {
+                                Some(leader) => format!("{} wins the match!", leader),
+                                None => "The match is a draw!".to_string(),
+                            };
+                            (
+                                heading,
+                                Some(format!(
+                                    "Press F5 to play again, Escape for the menu\n{}",
+                                    self.replay_hint()
+                                )),
+                            )
+                        }
+                    }
+                };
+
+                draw_text(canvas, &heading, 24.0, center, Vec2::new(0.5, 0.5));
+                if let Some(hint) = hint {
+                    draw_text(
+                        canvas,
+                        &hint,
+                        16.0,
+                        center + Vec2::new(0.0, 30.0),
+                        Vec2::new(0.5, 0.5),
+                    );
+                }
+            }
+        }
+    }
+
+    /// What can be done with the match replay once a round is over.
+    fn replay_hint(&self) -> String {
+        let hint = "R: replay the last shot  S: save the match replay".to_string();
+        match self.saved_replay.as_ref().and_then(|path| path.file_name()) {
+            Some(name) => format!("{}\nSaved {}", hint, name.to_string_lossy()),
+            None => hint,
+        }
+    }
+
+    /// Sets off `weapon`'s blast where a shot fired by `owner` landed. It blows
+    /// a crater and damages every tank other than the shooter by how close it
+    /// was, crediting the shooter with the damage dealt. The ground around
+    /// damaging blasts gets scorched.
+    fn explode(&mut self, owner: PlayerId, weapon: Weapon, impact: &Impact) {
+        let blast = weapon.blast();
+        let scorch_radius = (blast.max_damage > 0).then_some(blast.damage_radius);
+        self.terrain.deform(impact.pos, blast.crater_radius);
+        self.particles.explode(impact.pos, blast.crater_radius);
+        if let Some(radius) = scorch_radius {
+            self.terrain.scorch(impact.pos, radius);
+        }
+        if let Some(recording) = &mut self.recording {
+            recording.explosion(impact.pos, blast.crater_radius, scorch_radius);
+        }
+
+        let mut dealt = 0;
+        for (index, tank) in self.turns.tanks_mut().iter_mut().enumerate() {
+            let player = PlayerId(index);
+            if player == owner {
+                continue;
+            }
 
-        GameState { tank }
+            let distance = if impact.tank == Some(player) {
+                0.0
+            } else {
+                tank.distance_to(impact.pos)
+            };
+            // Overkill doesn't count towards the score.
+            let damage = blast.damage_at(distance).min(tank.health);
+            tank.health -= damage;
+            dealt += damage;
+            if damage > 0 {
+                if let Some(recording) = &mut self.recording {
+                    recording.damage(player, damage);
+                }
+            }
+        }
+        self.turns.tanks_mut()[owner.0].score += dealt;
+
+        // Anyone left standing over the crater drops down into it.
+        for tank in self.turns.tanks_mut() {
+            tank.rest_on(&self.terrain);
+        }
+    }
+
+    /// Moves the shells in the air along by one tick and sets off any that
+    /// landed. Returns whether the terrain changed.
+    fn update_projectiles(&mut self) -> bool {
+        // Shells that hit the ground or a tank explode, while those that
+        // leave the screen are simply gone. Cluster bombs are replaced by
+        // their fragments once they start falling.
+        let dt = 1.0 / self.settings.updates_per_second as f32;
+        let (terrain, tanks, wind) = (&self.terrain, self.turns.tanks(), self.wind);
+        let gravity = self.settings.gravity;
+        let mut impacts = Vec::new();
+        let mut fragments = Vec::new();
+        self.projectiles.retain_mut(|projectile| {
+            if let Some(impact) = projectile.update(dt, wind, gravity, terrain, tanks) {
+                impacts.push((projectile.owner, projectile.weapon, impact));
+                return false;
+            }
+            if let Some(split) = projectile.split() {
+                fragments.extend(split);
+                return false;
+            }
+            !projectile.is_off_screen(terrain.size())
+        });
+        self.projectiles.extend(fragments);
+        for (owner, weapon, impact) in &impacts {
+            self.explode(*owner, *weapon, impact);
+        }
+        if let Some(recording) = &mut self.recording {
+            recording.record(&self.projectiles);
+        }
+
+        !impacts.is_empty()
+    }
+
+    /// Wraps up a shot once it has fully resolved. The round is over as soon as
+    /// only one tank is left standing. Otherwise it's the next player's turn.
+    fn end_shot(&mut self) {
+        if let Some(shot) = self.recording.take() {
+            self.replay.shots.push(shot);
+        }
+
+        if let Some(winner) = self.turns.winner() {
+            self.match_state.finish_round(self.turns.tanks_mut());
+            self.phase = if self.match_state.is_over() {
+                Phase::GameOver
+            } else {
+                Phase::RoundOver { winner }
+            };
+            return;
+        }
+
+        self.terrain.fade_scorch();
+        self.wind = random_wind(&mut self.rng, self.settings.max_wind);
+        self.turns.end_turn();
+        self.phase = turn_transition(&self.settings);
+    }
+
+    /// Handles a key press on the start menu. The first player is always at
+    /// the keyboard, and everyone else is played by `opponents`.
+    fn menu_key_down(
+        &mut self,
+        ctx: &mut Context,
+        players: usize,
+        opponents: PlayerController,
+        key: KeyCode,
+    ) -> GameResult {
+        let (players, opponents) = match key {
+            KeyCode::Left => ((players - 1).max(PLAYER_RANGE.0), opponents),
+            KeyCode::Right => ((players + 1).min(PLAYER_RANGE.1), opponents),
+            KeyCode::Up | KeyCode::Down => (players, opponents.next()),
+            KeyCode::Return => {
+                let controllers = std::iter::once(PlayerController::Human)
+                    .chain(std::iter::repeat_n(opponents, players - 1))
+                    .collect();
+                let state =
+                    GameState::from_seed(ctx, self.seed, controllers, self.settings.clone())?;
+                self.replace(state);
+                return Ok(());
+            }
+            _ => (players, opponents),
+        };
+        self.phase = Phase::MainMenu { players, opponents };
+
+        Ok(())
+    }
+
+    /// Starts the current player's turn. Computer players work out their shot
+    /// up front and then line it up over the next few updates.
+    fn start_turn(&mut self) {
+        self.phase = Phase::Aiming;
+        self.turns.current_tank_mut().fuel = MAX_FUEL;
+        self.ai_shot = match self.turns.current_controller() {
+            PlayerController::Human => None,
+            PlayerController::Computer(difficulty) => ai::plan_shot(
+                difficulty,
+                self.turns.current_player(),
+                self.turns.tanks(),
+                &self.terrain,
+                self.wind,
+                &self.settings,
+                &mut self.rng,
+            ),
+        };
+    }
+
+    /// Fires the current player's tank, ending their part of the turn.
+    fn fire(&mut self) {
+        let projectile = self.turns.fire();
+        self.projectiles.push(projectile);
+        self.recording = Some(ShotReplay::new(
+            self.turns.current_player(),
+            self.wind,
+            &self.terrain,
+            self.turns.tanks(),
+            &self.projectiles,
+        ));
+        self.ai_shot = None;
+        self.phase = Phase::Firing;
+    }
+
+    /// Handles a key press from the player whose turn it is, going by the key
+    /// bindings in the settings.
+    fn aiming_key_down(&mut self, key: KeyCode) {
+        let Some(action) = self.settings.keys.action(key) else {
+            return;
+        };
+
+        let tank = self.turns.current_tank_mut();
+        match action {
+            Action::AimLeft => tank.aim(ANGLE_STEP, 0.0),
+            Action::AimRight => tank.aim(-ANGLE_STEP, 0.0),
+            Action::PowerUp => tank.aim(0.0, POWER_STEP),
+            Action::PowerDown => tank.aim(0.0, -POWER_STEP),
+            Action::PowerUpFast => tank.aim(0.0, POWER_PAGE_STEP),
+            Action::PowerDownFast => tank.aim(0.0, -POWER_PAGE_STEP),
+            // The tank keeps driving that way until the key is let go.
+            Action::Drive(direction) => tank.direction = Some(direction),
+            Action::NextWeapon => tank.next_weapon(),
+            Action::SelectWeapon(index) => {
+                if let Some(&weapon) = Weapon::ARSENAL.get(index) {
+                    tank.select_weapon(weapon);
+                }
+            }
+            Action::Fire => self.fire(),
+        }
     }
 }
 
@@ -118,18 +701,48 @@ impl event::EventHandler<ggez::GameError> for GameState {
         // Rely on ggez's built-in timer for deciding when to update the game, and how many times.
         // If the update is early, there will be no cycles, otherwises, the logic will run once for each
         // frame fitting in the time since the last update.
-        while ctx.time.check_update_time(8) {
-            if self.tank.pos.x < SCREEN_SIZE.0 as isize && self.tank.pos.y < SCREEN_SIZE.1 as isize
-            {
-                self.tank.update(Position {
-                    x: self.tank.pos.x + 10,
-                    y: self.tank.pos.y + 10,
-                });
+        let ups = self.settings.updates_per_second;
+        let dt = 1.0 / ups as f32;
+        while ctx.time.check_update_time(ups) {
+            // Effects play out whatever else is going on.
+            self.particles.update(dt, self.settings.gravity);
+
+            if let Some(playback) = &mut self.playback {
+                if !playback.update(ctx, &mut self.particles)? {
+                    self.playback = None;
+                }
+                continue;
+            }
+
+            match self.phase {
+                // Only the current player's tank gets to move, and only until
+                // it has fired. Computer players fire as soon as they are lined up.
+                Phase::Aiming => {
+                    let tank = self.turns.current_tank_mut();
+                    tank.update(dt, self.settings.tank_speed, &self.terrain);
+                    if let Some(shot) = self.ai_shot {
+                        if ai::aim_towards(tank, shot, dt) {
+                            self.fire();
+                        }
+                    }
+                }
+                Phase::Firing => {
+                    let mut terrain_changed = self.update_projectiles();
+                    if self.projectiles.is_empty() {
+                        self.end_shot();
+                        terrain_changed = true;
+                    }
+                    if terrain_changed {
+                        self.terrain_mesh = self.terrain.build_mesh(ctx)?;
+                    }
+                }
+                Phase::TurnTransition { ticks: 0 } => self.start_turn(),
+                Phase::TurnTransition { ticks } => {
+                    self.phase = Phase::TurnTransition { ticks: ticks - 1 }
+                }
+                // Nothing moves while waiting on the players.
+                Phase::MainMenu { .. } | Phase::RoundOver { .. } | Phase::GameOver => {}
             }
-            // We check to see if the game is over. If not, we'll update. If so, we'll just do nothing.
-            // Here we do the actual updating of our game world. First we tell the snake to update itself,
-            // passing in a reference to our piece of food.
-            // Next we check if the snake ate anything as it updated.
         }
 
         Ok(())
@@ -137,11 +750,61 @@ impl event::EventHandler<ggez::GameError> for GameState {
 
     /// draw is where we should actually render the game's current state.
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        let mut canvas = graphics::Canvas::from_frame(ctx, Color::from([1.0, 1.0, 1.0, 1.0]));
+        let background = Color::from([1.0, 1.0, 1.0, 1.0]);
+        // The battlefield is drawn a pixel to a pixel, resting on the bottom of
+        // the window. A window that isn't a whole number of grid cells across
+        // leaves a sliver of sky at the top and sides.
+        let field = self.terrain.size();
+        let (width, height) = ctx.gfx.drawable_size();
+        let screen = graphics::Rect::new((field.x - width) / 2.0, field.y - height, width, height);
 
-        self.tank.draw(&mut canvas);
+        if self.pixelated {
+            // The low-resolution image needs a pixel for every window pixel to
+            // land on, so it runs a little past the window unless the window
+            // divides evenly into `PIXEL_SCALE` pixel blocks.
+            let size =
+                window_to_canvas(Vec2::new(width, height) - Vec2::ONE, PIXEL_SCALE) + Vec2::ONE;
+            let image = self
+                .pixel_canvas
+                .get_or_insert_with(|| {
+                    graphics::Image::new_canvas_image(
+                        ctx,
+                        ctx.gfx.surface_format(),
+                        size.x as u32,
+                        size.y as u32,
+                        1,
+                    )
+                })
+                .clone();
 
-        canvas.finish(ctx)?;
+            // Render the world into the low-resolution image first, keeping the
+            // window's coordinate system so nothing else has to know about it.
+            let mut canvas = graphics::Canvas::from_image(ctx, image.clone(), background);
+            canvas.set_screen_coordinates(graphics::Rect::new(
+                screen.x,
+                screen.y,
+                size.x * PIXEL_SCALE as f32,
+                size.y * PIXEL_SCALE as f32,
+            ));
+            self.draw_world(&mut canvas);
+            canvas.finish(ctx)?;
+
+            // Then blow it back up to the window size without smoothing.
+            let mut canvas = graphics::Canvas::from_frame(ctx, background);
+            canvas.set_sampler(graphics::Sampler::nearest_clamp());
+            canvas.draw(
+                &image,
+                graphics::DrawParam::new().scale(Vec2::splat(PIXEL_SCALE as f32)),
+            );
+            canvas.finish(ctx)?;
+        } else {
+            let mut canvas = graphics::Canvas::from_frame(ctx, background);
+            canvas.set_screen_coordinates(screen);
+
+            self.draw_world(&mut canvas);
+
+            canvas.finish(ctx)?;
+        }
 
         ggez::timer::yield_now();
 
@@ -149,105 +812,570 @@ impl event::EventHandler<ggez::GameError> for GameState {
     }
 
     /// key_down_event gets fired when a key gets pressed.
-    fn key_down_event(&mut self, _ctx: &mut Context, input: KeyInput, _repeat: bool) -> GameResult {
-        // Here we attempt to convert the Keycode into a Direction using the helper
-        // we defined earlier.
-        if let Some(dir) = input.keycode.and_then(Direction::from_keycode) {
-            // If it succeeds, we check if a new direction has already been set
-            // and make sure the new direction is different then `snake.dir`
-            if let Some(last_known_dir) = self.tank.last_known_direction {
-                if self.tank.direction != last_known_dir && dir.inverse() != self.tank.direction {
-                    self.tank.next_direction = Some(dir);
-                } else if dir.inverse() != last_known_dir {
-                    // If no new direction has been set and the direction is not the inverse
-                    // of the `last_update_dir`, then set the snake's new direction to be the
-                    // direction the user pressed.
-                    self.tank.direction = dir;
+    ///
+    /// Default key bindings for whoever's turn it is, which can be changed in
+    /// the settings file:
+    /// - Left/Right: turn the turret towards the left or right
+    /// - Up/Down: raise or lower the shot power
+    /// - PageUp/PageDown: raise or lower the shot power in bigger steps
+    /// - A/D: drive the tank left or right
+    /// - Tab: switch to the next weapon with ammo left
+    /// - 1/2/3: pick a weapon directly, if it has ammo left
+    /// - Space: fire
+    ///
+    /// On the start menu, Left/Right pick the number of players, Up/Down pick
+    /// whether the other players are people or the computer, and Enter starts
+    /// the match. Enter also starts the next round once a round is over, and
+    /// Escape goes back to the menu once the match is over.
+    ///
+    /// Between turns and once a round is over, R replays the last shot. Up and
+    /// Down change the speed of a replay, and Escape or R stops it. Once a
+    /// round is over, S saves a replay of the whole match so far.
+    ///
+    /// At any time:
+    /// - F5: restart the match (asks first, unless the match is over)
+    /// - F1: toggle the HUD
+    /// - F2: toggle pixelated rendering
+    ///
+    /// During a player's turn, a key bound to one of their actions in the
+    /// settings does that instead of any of the above.
+    fn key_down_event(&mut self, ctx: &mut Context, input: KeyInput, _repeat: bool) -> GameResult {
+        let Some(key) = input.keycode else {
+            return Ok(());
+        };
+
+        // While the restart prompt is up, Y confirms and N or Escape backs out.
+        // Everything else is ignored so a stray key can't fire a shot.
+        if self.confirm_restart {
+            match key {
+                KeyCode::Y => self.restart(ctx)?,
+                KeyCode::N | KeyCode::Escape => self.confirm_restart = false,
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        // The keys bound in the settings come first during a player's own
+        // turn, so binding an action to a key the game also uses still works.
+        let own_turn = self.playback.is_none()
+            && self.phase == Phase::Aiming
+            && self.turns.current_controller() == PlayerController::Human;
+        if own_turn && self.settings.keys.action(key).is_some() {
+            self.aiming_key_down(key);
+            return Ok(());
+        }
+
+        // F1 toggles the HUD, and F2 the retro pixelated rendering mode.
+        if key == KeyCode::F1 {
+            self.show_hud = !self.show_hud;
+            return Ok(());
+        }
+        if key == KeyCode::F2 {
+            self.pixelated = !self.pixelated;
+            return Ok(());
+        }
+
+        // A replay keeps the keys to itself until it is stopped.
+        if let Some(playback) = &mut self.playback {
+            match key {
+                KeyCode::Up => playback.faster(),
+                KeyCode::Down => playback.slower(),
+                KeyCode::Escape | KeyCode::R => self.playback = None,
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        match self.phase {
+            Phase::MainMenu { players, opponents } => {
+                self.menu_key_down(ctx, players, opponents, key)?
+            }
+            Phase::GameOver => match key {
+                KeyCode::F5 => self.restart(ctx)?,
+                KeyCode::R => self.instant_replay(ctx)?,
+                KeyCode::S => self.save_replay(),
+                KeyCode::Escape => {
+                    let state = GameState::new(ctx, self.settings.clone())?;
+                    self.replace(state);
                 }
+                _ => {}
+            },
+            // F5 asks before restarting the match from the same seed.
+            _ if key == KeyCode::F5 => self.confirm_restart = true,
+            // There's no replaying a shot that is still in the air.
+            Phase::Aiming | Phase::TurnTransition { .. } | Phase::RoundOver { .. }
+                if key == KeyCode::R =>
+            {
+                self.instant_replay(ctx)?
             }
+            Phase::RoundOver { .. } if key == KeyCode::Return => self.start_round(ctx)?,
+            Phase::RoundOver { .. } if key == KeyCode::S => self.save_replay(),
+            // Computer players don't take orders from the keyboard.
+            Phase::Aiming if self.turns.current_controller() == PlayerController::Human => {
+                self.aiming_key_down(key)
+            }
+            Phase::Aiming
+            | Phase::Firing
+            | Phase::TurnTransition { .. }
+            | Phase::RoundOver { .. } => {}
         }
+
         Ok(())
     }
+
+    /// key_up_event gets fired when a key gets released.
+    fn key_up_event(&mut self, _ctx: &mut Context, input: KeyInput) -> GameResult {
+        // Letting go of the key the current tank is driving with stops it.
+        if self.phase == Phase::Aiming {
+            let tank = self.turns.current_tank_mut();
+            let keys = &self.settings.keys;
+            if input.keycode.and_then(|key| keys.direction(key)) == tank.direction {
+                tank.direction = None;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sets up `players` tanks for a round on `terrain`. The outermost two start an
+/// eighth of the way in from opposite edges, with the rest spread out evenly
+/// between them. Every tank starts out aiming towards the middle.
+fn spawn_tanks(terrain: &Terrain, players: usize) -> Vec<Tank> {
+    let colors = [
+        Color::from_rgb(255, 128, 0),
+        Color::from_rgb(0, 100, 255),
+        Color::from_rgb(160, 60, 200),
+        Color::from_rgb(200, 40, 40),
+    ];
+
+    let columns = terrain.width();
+    let (first, last) = (columns / 8, columns - 1 - columns / 8);
+    (0..players)
+        .map(|index| {
+            let column = first + (last - first) * index as isize / (players as isize - 1).max(1);
+            let x = (column as f32 + 0.5) * GRID_CELL_SIZE as f32;
+            let mut tank = Tank::new(x, colors[index % colors.len()]);
+            if column > columns / 2 {
+                tank.angle = std::f32::consts::PI - tank.angle;
+            }
+            tank.rest_on(terrain);
+            tank
+        })
+        .collect()
 }
 
 impl Tank {
-    pub fn new(pos: Position, direction: Direction) -> Self {
+    /// A tank centered on the pixel column `x`. It still needs to be put down on
+    /// the ground with `rest_on`.
+    pub fn new(x: f32, color: Color) -> Self {
         Tank {
-            pos,
-            last_known_position: None,
-            next_direction: None,
-            direction,
-            last_known_direction: None,
-            next_pos: None,
+            pos: Vec2::new(x, 0.0),
+            tilt: 0.0,
+            color,
+            health: MAX_HEALTH,
+            score: 0,
+            angle: std::f32::consts::FRAC_PI_4,
+            power: DEFAULT_POWER,
+            weapon: Weapon::Shell,
+            ammo: Weapon::ARSENAL.map(|weapon| weapon.starting_ammo()),
+            direction: None,
+            fuel: MAX_FUEL,
         }
     }
 
     pub fn draw(&self, canvas: &mut Canvas) {
-        println!("Drawing tank at {:?}", self.pos);
-        // draw tank
+        // Destroyed tanks are left as a burnt out wreck, without a turret.
+        let destroyed = self.health <= 0;
+
+        // draw tank, tilted to sit flat on the slope
         canvas.draw(
             &graphics::Quad,
             graphics::DrawParam::new()
-                .dest_rect(self.pos.into())
-                .color([1.0, 0.5, 0.0, 1.0]),
+                .dest(self.pos)
+                .offset(Vec2::new(0.5, 0.5))
+                .scale(Vec2::new(TANK_SIZE.0, TANK_SIZE.1))
+                .rotation(self.tilt)
+                .color(if destroyed {
+                    Color::from_rgb(70, 70, 70)
+                } else {
+                    self.color
+                }),
         );
+        if destroyed {
+            return;
+        }
 
-        // draw turret
+        // draw turret, pivoting on the middle of the body. Positive rotations
+        // go clockwise on screen, hence the negated angle.
         canvas.draw(
             &graphics::Quad,
-            graphics::DrawParam::new().dest_rect(self.pos.into()),
+            graphics::DrawParam::new()
+                .dest(self.center())
+                .offset(Vec2::new(0.0, 0.5))
+                .scale(Vec2::new(TURRET_SIZE.0, TURRET_SIZE.1))
+                .rotation(-self.angle)
+                .color(Color::BLACK),
         );
     }
 
-    pub fn update(&mut self, new_pos: Position) {
-        if let Some(ref mut last_known_pos) = &mut self.last_known_position {
-            if last_known_pos == &mut self.pos && self.next_pos.is_some() {
-                self.pos = self.next_pos.take().unwrap();
-            } else {
-                self.last_known_position = Some(self.pos);
-                self.pos = new_pos;
-            }
-        }
-        let new_pos = Position::new_move(new_pos, self.direction);
+    /// Turns the turret by `angle` radians and changes the power by `power`.
+    /// The turret can't point below the horizon, and power stays within
+    /// `POWER_RANGE`.
+    pub fn aim(&mut self, angle: f32, power: f32) {
+        self.angle = (self.angle + angle).clamp(0.0, std::f32::consts::PI);
+        self.power = (self.power + power).clamp(POWER_RANGE.0, POWER_RANGE.1);
+    }
 
-        self.next_pos = Some(new_pos);
+    /// Puts the tank down on the ground where it is, tilted to match the
+    /// slope between where its two ends touch down.
+    pub fn rest_on(&mut self, terrain: &Terrain) {
+        let left = terrain.surface_at(self.pos.x - TANK_SIZE.0 / 2.0);
+        let right = terrain.surface_at(self.pos.x + TANK_SIZE.0 / 2.0);
+        self.tilt = (right - left).atan2(TANK_SIZE.0);
+        self.pos.y = (left + right) / 2.0 - TANK_SIZE.1 / 2.0;
+    }
 
-        if let Some(ref mut last_known_dir) = &mut self.last_known_direction {
-            if last_known_dir == &mut self.direction && self.next_direction.is_some() {
-                self.direction = self.next_direction.take().unwrap();
-            } else {
-                self.last_known_direction = Some(self.direction);
-            }
+    /// Shots of `weapon` the tank has left, or `None` if there's no limit.
+    pub fn ammo(&self, weapon: Weapon) -> Option<u32> {
+        Weapon::ARSENAL
+            .iter()
+            .position(|&other| other == weapon)
+            .and_then(|index| self.ammo[index])
+    }
+
+    /// Switches to `weapon`, unless the tank has run out of it.
+    pub fn select_weapon(&mut self, weapon: Weapon) {
+        if self.ammo(weapon) != Some(0) {
+            self.weapon = weapon;
+        }
+    }
+
+    /// Switches to the next weapon in the arsenal the tank still has ammo for.
+    pub fn next_weapon(&mut self) {
+        let mut weapon = self.weapon.next();
+        while self.ammo(weapon) == Some(0) {
+            weapon = weapon.next();
         }
+        self.weapon = weapon;
+    }
 
-        if let Some(ref mut next_dir) = &mut self.next_direction {
-            if next_dir.inverse() != self.direction {
-                self.direction = *next_dir;
+    /// Uses up one shot of the current weapon. Once it runs out the tank falls
+    /// back to the first weapon in the arsenal, which never does.
+    pub fn use_ammo(&mut self) {
+        let Some(index) = Weapon::ARSENAL
+            .iter()
+            .position(|&weapon| weapon == self.weapon)
+        else {
+            return;
+        };
+        if let Some(ammo) = &mut self.ammo[index] {
+            *ammo = ammo.saturating_sub(1);
+            if *ammo == 0 {
+                self.weapon = Weapon::ARSENAL[0];
             }
         }
+    }
 
-        self.next_direction = None;
+    /// The tank's body, ignoring its tilt.
+    pub fn body(&self) -> graphics::Rect {
+        graphics::Rect::new(
+            self.pos.x - TANK_SIZE.0 / 2.0,
+            self.pos.y - TANK_SIZE.1 / 2.0,
+            TANK_SIZE.0,
+            TANK_SIZE.1,
+        )
+    }
+
+    /// Whether the pixel-space point `pos` lies within the tank's body.
+    pub fn contains(&self, pos: Vec2) -> bool {
+        self.body().contains(pos)
+    }
+
+    /// Distance in pixels from `pos` to the nearest edge of the tank's body,
+    /// or zero if `pos` is inside it.
+    pub fn distance_to(&self, pos: Vec2) -> f32 {
+        let rect = self.body();
+        let nearest = Vec2::new(
+            pos.x.clamp(rect.left(), rect.right()),
+            pos.y.clamp(rect.top(), rect.bottom()),
+        );
+        pos.distance(nearest)
+    }
+
+    /// Center of the tank body in pixels.
+    pub fn center(&self) -> Vec2 {
+        self.pos
+    }
 
-        self.pos = Position::new_move(self.pos, self.direction);
+    /// Where a fired projectile starts: the end of the turret along `angle`.
+    pub fn turret_tip(&self) -> Vec2 {
+        self.center() + Vec2::new(self.angle.cos(), -self.angle.sin()) * TURRET_SIZE.0
+    }
+
+    /// Initial velocity of a projectile fired with the current angle and power.
+    /// Screen y grows downwards, hence the negated vertical component.
+    pub fn launch_velocity(&self) -> Vec2 {
+        Vec2::new(
+            self.power * self.angle.cos(),
+            -self.power * self.angle.sin(),
+        )
+    }
+
+    /// Drives the tank at `speed` pixels per second for `dt` seconds in the
+    /// direction it is being driven, following the ground. It stops at the
+    /// edges of the playing field, at slopes too steep to climb and once it
+    /// runs out of fuel.
+    pub fn update(&mut self, dt: f32, speed: f32, terrain: &Terrain) {
+        let Some(direction) = self.direction else {
+            return;
+        };
+
+        let step = (speed * dt).min(self.fuel);
+        let x = match direction {
+            Direction::Left => self.pos.x - step,
+            Direction::Right => self.pos.x + step,
+        };
+        let x = x.clamp(TANK_SIZE.0 / 2.0, terrain.size().x - TANK_SIZE.0 / 2.0);
+
+        let climb = terrain.surface_at(self.pos.x) - terrain.surface_at(x);
+        if climb > MAX_CLIMB * (x - self.pos.x).abs() {
+            return;
+        }
+
+        self.fuel -= (x - self.pos.x).abs();
+        self.pos.x = x;
+        self.rest_on(terrain);
     }
 }
 
 pub fn main() -> GameResult {
+    let mut settings = Settings::load();
+
     // Here we use a ContextBuilder to setup metadata about our game. First the title and author
-    let (ctx, events_loop) = ggez::ContextBuilder::new("pockettanks", "Utsav Balar")
+    let (mut ctx, events_loop) = ggez::ContextBuilder::new("pockettanks", "Utsav Balar")
         // Next we set up the window. This title will be displayed in the title bar of the window.
         .window_setup(ggez::conf::WindowSetup::default().title("Pocket Tanks!"))
-        // Now we get to set the size of the window, which we use our SCREEN_SIZE constant from earlier to help with
+        // Now we get to set the size of the window, which comes from the settings
         .window_mode(
             ggez::conf::WindowMode::default()
-                .dimensions(SCREEN_SIZE.0 as f32, SCREEN_SIZE.1 as f32),
+                .dimensions(settings.window.width, settings.window.height)
+                .fullscreen_type(if settings.window.fullscreen {
+                    ggez::conf::FullscreenType::Desktop
+                } else {
+                    ggez::conf::FullscreenType::Windowed
+                }),
         )
         // And finally we attempt to build the context and create the window. If it fails, we panic with the message
         // "Failed to build ggez context"
         .build()?;
 
+    // A fullscreen window takes up the whole desktop, so the battlefield does too.
+    if settings.window.fullscreen {
+        let (width, height) = ctx.gfx.drawable_size();
+        settings.window.width = width;
+        settings.window.height = height;
+    }
+
     // Next we create a new instance of our GameState struct, which implements EventHandler
-    let state = GameState::new();
+    let mut state = GameState::new(&mut ctx, settings)?;
+
+    // A replay file passed on the command line gets played before the menu.
+    if let Some(path) = std::env::args_os().nth(1) {
+        match MatchReplay::load(Path::new(&path)) {
+            Ok(replay) => state.watch(&mut ctx, &replay)?,
+            Err(err) => println!("Could not load {}: {}", Path::new(&path).display(), err),
+        }
+    }
 
     event::run(ctx, events_loop, state)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tank resting on `terrain` at the pixel column `x`.
+    fn tank_at(terrain: &Terrain, x: f32) -> Tank {
+        let mut tank = Tank::new(x, Color::WHITE);
+        tank.rest_on(terrain);
+        tank
+    }
+
+    /// Drives `tank` one grid cell in `direction` on `terrain`.
+    fn drive_one_cell(tank: &mut Tank, direction: Direction, terrain: &Terrain) {
+        tank.direction = Some(direction);
+        tank.update(1.0, GRID_CELL_SIZE as f32, terrain);
+    }
+
+    #[test]
+    fn driving_left_moves_one_cell_left() {
+        let terrain = Terrain::flat((48, 48), 10);
+        let mut tank = tank_at(&terrain, 245.0);
+        let start = tank.pos;
+        drive_one_cell(&mut tank, Direction::Left, &terrain);
+        assert_eq!(tank.pos, start - Vec2::new(GRID_CELL_SIZE as f32, 0.0));
+    }
+
+    #[test]
+    fn driving_right_moves_one_cell_right() {
+        let terrain = Terrain::flat((48, 48), 10);
+        let mut tank = tank_at(&terrain, 245.0);
+        let start = tank.pos;
+        drive_one_cell(&mut tank, Direction::Right, &terrain);
+        assert_eq!(tank.pos, start + Vec2::new(GRID_CELL_SIZE as f32, 0.0));
+    }
+
+    #[test]
+    fn driving_there_and_back_returns_to_the_start() {
+        let terrain = Terrain::generate(&mut oorandom::Rand32::new(7), (48, 48));
+        let mut tank = tank_at(&terrain, 245.0);
+        let start = (tank.pos, tank.tilt);
+        for (there, back) in [
+            (Direction::Left, Direction::Right),
+            (Direction::Right, Direction::Left),
+        ] {
+            tank.fuel = MAX_FUEL;
+            drive_one_cell(&mut tank, there, &terrain);
+            drive_one_cell(&mut tank, back, &terrain);
+            assert_eq!((tank.pos, tank.tilt), start);
+        }
+    }
+
+    #[test]
+    fn fixed_ticks_drive_a_cell_each_until_the_wall() {
+        let terrain = Terrain::flat((48, 48), 10);
+        let cell = GRID_CELL_SIZE as f32;
+        let mut tank = tank_at(&terrain, 5.5 * cell);
+        tank.fuel = f32::INFINITY;
+        tank.direction = Some(Direction::Right);
+
+        // Each tick at a cell per tick moves exactly one more cell.
+        let start = tank.pos.x;
+        for tick in 1..=20 {
+            tank.update(1.0, cell, &terrain);
+            assert_eq!(tank.pos.x, start + tick as f32 * cell);
+        }
+
+        // Then it comes to a halt against the right edge and stays there.
+        for _ in 0..100 {
+            tank.update(1.0, cell, &terrain);
+        }
+        assert_eq!(tank.pos.x, terrain.size().x - TANK_SIZE.0 / 2.0);
+        tank.update(1.0, cell, &terrain);
+        assert_eq!(tank.pos.x, terrain.size().x - TANK_SIZE.0 / 2.0);
+    }
+
+    #[test]
+    fn window_pixels_map_onto_the_pixel_canvas() {
+        for scale in [1, 2, 4, 8] {
+            let block = scale as f32;
+            assert_eq!(window_to_canvas(Vec2::ZERO, scale), Vec2::ZERO);
+
+            // Every window pixel in a block lands on the same canvas pixel.
+            let corner = Vec2::new(3.0, 5.0) * block;
+            let last = corner + Vec2::splat(block - 1.0);
+            assert_eq!(window_to_canvas(corner, scale), Vec2::new(3.0, 5.0));
+            assert_eq!(window_to_canvas(last, scale), Vec2::new(3.0, 5.0));
+            assert_eq!(
+                window_to_canvas(last + Vec2::ONE, scale),
+                Vec2::new(4.0, 6.0)
+            );
+        }
+
+        // A window that doesn't divide evenly still has every pixel on canvas.
+        let window = Vec2::new(482.0, 480.0);
+        let size = window_to_canvas(window - Vec2::ONE, 4) + Vec2::ONE;
+        assert_eq!(size, Vec2::new(121.0, 120.0));
+    }
+
+    #[test]
+    fn layers_are_drawn_world_first_then_hud_then_overlay() {
+        let mut drawn = Vec::new();
+        Layer::draw_in_order(|layer| drawn.push(layer));
+        assert_eq!(
+            drawn,
+            [
+                Layer::Terrain,
+                Layer::Tanks,
+                Layer::Projectiles,
+                Layer::Effects,
+                Layer::Hud,
+                Layer::Overlay,
+            ]
+        );
+
+        // The HUD goes over everything in the world, and the overlay over that.
+        let position = |layer| drawn.iter().position(|&drawn| drawn == layer).unwrap();
+        for world in [
+            Layer::Terrain,
+            Layer::Tanks,
+            Layer::Projectiles,
+            Layer::Effects,
+        ] {
+            assert!(position(world) < position(Layer::Hud));
+        }
+        assert_eq!(position(Layer::Overlay), drawn.len() - 1);
+    }
+
+    #[test]
+    fn the_same_seed_sets_up_the_same_match() {
+        let settings = Settings::default();
+        for players in PLAYER_RANGE.0..=PLAYER_RANGE.1 {
+            let setup = MatchSetup::new(42, players, &settings);
+            assert_eq!(setup, MatchSetup::new(42, players, &settings));
+            assert_ne!(setup, MatchSetup::new(43, players, &settings));
+        }
+    }
+
+    fn settings_with_width(width: f32) -> Settings {
+        let mut settings = Settings::default();
+        settings.window.width = width;
+        settings
+    }
+
+    #[test]
+    fn wider_windows_get_proportionally_longer_terrain() {
+        let narrow = grid_size(&settings_with_width(480.0));
+        let wide = grid_size(&settings_with_width(960.0));
+        assert_eq!(wide, (2 * narrow.0, narrow.1));
+
+        let terrain = Terrain::generate(&mut oorandom::Rand32::new(1), wide);
+        assert_eq!(terrain.width(), wide.0);
+        assert_eq!(terrain.size(), Vec2::new(960.0, 480.0));
+    }
+
+    #[test]
+    fn taller_windows_get_a_taller_battlefield() {
+        let mut settings = Settings::default();
+        settings.window.height = 720.0;
+        let terrain = Terrain::generate(&mut oorandom::Rand32::new(1), grid_size(&settings));
+        assert_eq!(terrain.size(), Vec2::new(480.0, 720.0));
+
+        // The hills stay within the same share of the battlefield.
+        for x in 0..terrain.width() {
+            assert!((9..=36).contains(&terrain.height_at(x)));
+        }
+    }
+
+    #[test]
+    fn tanks_spawn_spread_evenly_across_the_battlefield() {
+        let cell = GRID_CELL_SIZE as f32;
+        for columns in [48, 96, 160] {
+            let terrain = Terrain::generate(&mut oorandom::Rand32::new(1), (columns, 48));
+            let xs: Vec<f32> = spawn_tanks(&terrain, 4)
+                .iter()
+                .map(|tank| tank.pos.x)
+                .collect();
+
+            // The outermost tanks start an eighth of the way in from either edge.
+            assert_eq!(xs[0], ((columns / 8) as f32 + 0.5) * cell);
+            assert_eq!(xs[3], ((columns - 1 - columns / 8) as f32 + 0.5) * cell);
+
+            // The ones in between are spaced evenly, give or take a column.
+            let gaps: Vec<f32> = xs.windows(2).map(|pair| pair[1] - pair[0]).collect();
+            for gap in &gaps {
+                assert!((gap - gaps[0]).abs() <= cell, "{:?}", gaps);
+            }
+        }
+    }
+}
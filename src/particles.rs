@@ -0,0 +1,151 @@
+use ggez::{
+    glam::*,
+    graphics::{self, Canvas, Color},
+    Context, GameResult,
+};
+
+/// How long the fireball of an explosion lasts, in seconds.
+const FIREBALL_LIFETIME: f32 = 0.3;
+
+/// How long smoke lingers after an explosion, in seconds.
+const SMOKE_LIFETIME: f32 = 1.2;
+
+/// How long dirt thrown up by an explosion stays around, in seconds.
+const DEBRIS_LIFETIME: f32 = 0.8;
+
+/// Pieces of dirt thrown up per pixel of crater radius.
+const DEBRIS_PER_RADIUS: f32 = 0.5;
+
+/// Smoke puffs left behind per pixel of crater radius.
+const SMOKE_PER_RADIUS: f32 = 0.25;
+
+/// A single blob of fire, smoke or dirt. Particles are purely for show and
+/// never affect the game.
+#[derive(PartialEq, Debug, Clone)]
+struct Particle {
+    pos: Vec2,
+    /// In pixels per second.
+    velocity: Vec2,
+    /// In pixels.
+    radius: f32,
+    /// How fast the radius changes, in pixels per second.
+    growth: f32,
+    color: Color,
+    /// How much of the pull of gravity the particle feels.
+    weight: f32,
+    /// Seconds since the particle was spawned.
+    age: f32,
+    /// Seconds the particle lasts in total. It fades out over its lifetime.
+    lifetime: f32,
+}
+
+/// Every particle on screen. They are all drawn in one go as instances of the
+/// same circle mesh, so lots of them at once stay cheap.
+pub struct Particles {
+    particles: Vec<Particle>,
+    /// Where each particle gets drawn this frame, rebuilt after every update.
+    instances: graphics::InstanceArray,
+    /// A white circle with a radius of one pixel, scaled and tinted per particle.
+    circle: graphics::Mesh,
+    /// Particles get their own RNG so the way explosions look can't change how
+    /// the match plays out.
+    rng: oorandom::Rand32,
+}
+
+impl Particles {
+    pub fn new(ctx: &mut Context, seed: u64) -> GameResult<Self> {
+        Ok(Particles {
+            particles: Vec::new(),
+            instances: graphics::InstanceArray::new(ctx, None),
+            circle: graphics::Mesh::new_circle(
+                ctx,
+                graphics::DrawMode::fill(),
+                Vec2::ZERO,
+                1.0,
+                0.01,
+                Color::WHITE,
+            )?,
+            rng: oorandom::Rand32::new(seed),
+        })
+    }
+
+    /// Spawns the effects for an explosion at `pos` that blew a crater of
+    /// `radius` pixels: a quickly expanding fireball, a spray of dirt and some
+    /// smoke drifting up afterwards.
+    pub fn explode(&mut self, pos: Vec2, radius: f32) {
+        self.particles.push(Particle {
+            pos,
+            velocity: Vec2::ZERO,
+            radius: radius * 0.3,
+            growth: radius * 0.7 / FIREBALL_LIFETIME,
+            color: Color::from_rgb(255, 160, 40),
+            weight: 0.0,
+            age: 0.0,
+            lifetime: FIREBALL_LIFETIME,
+        });
+
+        for _ in 0..(radius * DEBRIS_PER_RADIUS).ceil() as usize {
+            // Dirt flies out upwards, anywhere from straight up to sideways.
+            let angle = std::f32::consts::PI * self.rng.rand_float();
+            let speed = radius * (2.0 + 4.0 * self.rng.rand_float());
+            self.particles.push(Particle {
+                pos,
+                velocity: Vec2::new(angle.cos(), -angle.sin()) * speed,
+                radius: 1.0 + self.rng.rand_float(),
+                growth: 0.0,
+                color: Color::from_rgb(110, 80, 40),
+                weight: 1.0,
+                age: 0.0,
+                lifetime: DEBRIS_LIFETIME,
+            });
+        }
+
+        for _ in 0..(radius * SMOKE_PER_RADIUS).ceil() as usize {
+            let offset = Vec2::new(self.rng.rand_float() - 0.5, self.rng.rand_float() - 0.5);
+            self.particles.push(Particle {
+                pos: pos + offset * radius,
+                velocity: Vec2::new(offset.x * 10.0, -15.0 - 10.0 * self.rng.rand_float()),
+                radius: radius * 0.2,
+                growth: radius * 0.3,
+                color: Color::from_rgb(120, 120, 120),
+                weight: 0.0,
+                age: 0.0,
+                lifetime: SMOKE_LIFETIME * (0.75 + 0.5 * self.rng.rand_float()),
+            });
+        }
+    }
+
+    /// Moves every particle along by `dt` seconds under `gravity` and drops the
+    /// ones that have burnt out.
+    pub fn update(&mut self, dt: f32, gravity: f32) {
+        for particle in &mut self.particles {
+            particle.velocity.y += gravity * particle.weight * dt;
+            particle.pos += particle.velocity * dt;
+            particle.radius = (particle.radius + particle.growth * dt).max(0.0);
+            particle.age += dt;
+        }
+        self.particles
+            .retain(|particle| particle.age < particle.lifetime);
+
+        self.instances.set(self.particles.iter().map(|particle| {
+            let mut color = particle.color;
+            color.a *= 1.0 - particle.age / particle.lifetime;
+            graphics::DrawParam::new()
+                .dest(particle.pos)
+                .scale(Vec2::splat(particle.radius))
+                .color(color)
+        }));
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas) {
+        if self.particles.is_empty() {
+            return;
+        }
+
+        canvas.draw_instanced_mesh(
+            self.circle.clone(),
+            &self.instances,
+            graphics::DrawParam::new(),
+        );
+    }
+}
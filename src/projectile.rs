@@ -0,0 +1,180 @@
+use std::collections::VecDeque;
+
+use ggez::{
+    glam::*,
+    graphics::{self, Canvas, Color},
+};
+
+use crate::{terrain::Terrain, turn::PlayerId, weapons::Weapon, Tank};
+
+/// Side length of a projectile's square, in pixels.
+const PROJECTILE_SIZE: f32 = 4.0;
+
+/// Side length of each dot in a projectile's trail, in pixels.
+const TRAIL_DOT_SIZE: f32 = 2.0;
+
+/// How many past positions are kept to draw the trail behind a projectile.
+const TRAIL_LENGTH: usize = 120;
+
+/// Furthest a projectile moves between two collision checks, in pixels. A tick
+/// is long enough for a fast shell to cover several grid cells, so the move is
+/// split up to keep it from skipping through a thin hill or a tank.
+const COLLISION_STEP: f32 = 2.0;
+
+/// Horizontal speed difference between neighboring fragments when a projectile
+/// splits, in pixels per second.
+const FRAGMENT_SPREAD: f32 = 30.0;
+
+/// Where a projectile ran into something, and which tank it hit if it wasn't
+/// the ground.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Impact {
+    pub pos: Vec2,
+    pub tank: Option<PlayerId>,
+}
+
+/// A shell in flight. Unlike tanks, projectiles live in pixel space so they can
+/// follow a smooth arc instead of hopping between grid cells.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Projectile {
+    pub pos: Vec2,
+    pub velocity: Vec2,
+    /// The player whose tank fired this projectile.
+    pub owner: PlayerId,
+    pub weapon: Weapon,
+    /// Recent positions, oldest first.
+    trail: VecDeque<Vec2>,
+}
+
+impl Projectile {
+    pub fn new(pos: Vec2, velocity: Vec2, owner: PlayerId, weapon: Weapon) -> Self {
+        Projectile {
+            pos,
+            velocity,
+            owner,
+            weapon,
+            trail: VecDeque::with_capacity(TRAIL_LENGTH),
+        }
+    }
+
+    /// Advances the projectile by `dt` seconds under `wind` and `gravity`, a
+    /// horizontal and a downward acceleration in pixels per second squared.
+    /// Returns where it
+    /// hit if it ran into the ground or a tank on the way, in which case it
+    /// stops there.
+    pub fn update(
+        &mut self,
+        dt: f32,
+        wind: f32,
+        gravity: f32,
+        terrain: &Terrain,
+        tanks: &[Tank],
+    ) -> Option<Impact> {
+        self.velocity += Vec2::new(wind, gravity) * dt;
+
+        let start = self.pos;
+        let travel = self.velocity * dt;
+        let steps = (travel.length() / COLLISION_STEP).ceil().max(1.0) as usize;
+        for step in 1..=steps {
+            if self.trail.len() == TRAIL_LENGTH {
+                self.trail.pop_front();
+            }
+            self.trail.push_back(self.pos);
+
+            self.pos = start + travel * (step as f32 / steps as f32);
+            if let Some(impact) = self.collision(terrain, tanks) {
+                return Some(impact);
+            }
+        }
+
+        None
+    }
+
+    /// Breaks the projectile up into its weapon's fragments once it has passed
+    /// the top of its arc. Returns `None` while it should stay in one piece.
+    pub fn split(&self) -> Option<Vec<Projectile>> {
+        let (fragment, count) = self.weapon.fragments()?;
+        if self.velocity.y < 0.0 {
+            return None;
+        }
+
+        // Fan the fragments out evenly to either side of the original path.
+        let middle = (count as f32 - 1.0) / 2.0;
+        let fragments = (0..count)
+            .map(|index| {
+                let spread = Vec2::new((index as f32 - middle) * FRAGMENT_SPREAD, 0.0);
+                let mut projectile =
+                    Projectile::new(self.pos, self.velocity + spread, self.owner, fragment);
+                projectile.trail = self.trail.clone();
+                projectile
+            })
+            .collect();
+
+        Some(fragments)
+    }
+
+    /// What the projectile is touching right now, if anything. Wrecks of
+    /// destroyed tanks don't get in the way.
+    fn collision(&self, terrain: &Terrain, tanks: &[Tank]) -> Option<Impact> {
+        if let Some(index) = tanks
+            .iter()
+            .position(|tank| tank.health > 0 && tank.contains(self.pos))
+        {
+            return Some(Impact {
+                pos: self.pos,
+                tank: Some(PlayerId(index)),
+            });
+        }
+
+        terrain.is_solid(self.pos).then_some(Impact {
+            pos: self.pos,
+            tank: None,
+        })
+    }
+
+    /// Whether the projectile has fallen below a battlefield of `field` pixels
+    /// or left it through either side. Leaving through the top is fine, since
+    /// it will come back down.
+    pub fn is_off_screen(&self, field: Vec2) -> bool {
+        self.pos.y > field.y || self.pos.x < 0.0 || self.pos.x > field.x
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas) {
+        // The trail gets fainter the older it is.
+        for (age, &pos) in self.trail.iter().rev().enumerate() {
+            draw_trail_dot(canvas, pos, 1.0 - age as f32 / TRAIL_LENGTH as f32);
+        }
+        draw_shell(canvas, self.pos);
+    }
+}
+
+/// Draws a projectile at `pos`.
+pub fn draw_shell(canvas: &mut Canvas, pos: Vec2) {
+    canvas.draw(
+        &graphics::Quad,
+        graphics::DrawParam::new()
+            .dest_rect(graphics::Rect::new(
+                pos.x - PROJECTILE_SIZE / 2.0,
+                pos.y - PROJECTILE_SIZE / 2.0,
+                PROJECTILE_SIZE,
+                PROJECTILE_SIZE,
+            ))
+            .color(Color::BLACK),
+    );
+}
+
+/// Draws one dot of a trail at `pos`, with `alpha` going from 0 for invisible
+/// to 1 for solid.
+pub fn draw_trail_dot(canvas: &mut Canvas, pos: Vec2, alpha: f32) {
+    canvas.draw(
+        &graphics::Quad,
+        graphics::DrawParam::new()
+            .dest_rect(graphics::Rect::new(
+                pos.x - TRAIL_DOT_SIZE / 2.0,
+                pos.y - TRAIL_DOT_SIZE / 2.0,
+                TRAIL_DOT_SIZE,
+                TRAIL_DOT_SIZE,
+            ))
+            .color(Color::new(0.5, 0.5, 0.5, alpha)),
+    );
+}
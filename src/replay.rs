@@ -0,0 +1,547 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ggez::{
+    glam::*,
+    graphics::{self, Canvas, Color},
+    Context, GameResult,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    draw_text,
+    particles::Particles,
+    projectile::{self, Projectile},
+    settings::MAX_UPDATES_PER_SECOND,
+    terrain::Terrain,
+    turn::PlayerId,
+    Layer, Tank, MAX_HEALTH, PLAYER_RANGE,
+};
+
+/// Slowest and fastest a replay can be played, as multiples of the speed it
+/// was recorded at.
+const SPEED_RANGE: (f32, f32) = (0.25, 4.0);
+
+/// How long the end of each shot stays on screen before the replay moves on,
+/// in seconds of the recording.
+const END_PAUSE: f32 = 1.0;
+
+/// Longest a shot in a replay file can last, in seconds. Real shots are over
+/// long before this.
+const MAX_SHOT_TIME: f32 = 120.0;
+
+/// Most projectiles a replay file can have in the air at once.
+const MAX_PROJECTILES: usize = 64;
+
+/// Biggest crater or scorch mark a replay file can have, in pixels.
+const MAX_BLAST_RADIUS: f32 = 200.0;
+
+/// A tank as it stood when a shot was fired. It gets put back down on the
+/// recorded terrain during playback, which puts it exactly where it was.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+struct TankFrame {
+    x: f32,
+    angle: f32,
+    color: Color,
+    health: i32,
+}
+
+/// Something that happened to the world while a shot was in the air.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+enum ReplayEvent {
+    /// A blast at `pos` blew a crater of `crater_radius` pixels, and scorched
+    /// the ground out to `scorch_radius` pixels if it could do damage.
+    Explosion {
+        pos: [f32; 2],
+        crater_radius: f32,
+        scorch_radius: Option<f32>,
+    },
+    /// `player`'s tank lost `amount` health.
+    Damage { player: usize, amount: i32 },
+}
+
+/// An event along with the tick of the shot it happened on.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+struct TimedEvent {
+    tick: usize,
+    event: ReplayEvent,
+}
+
+/// Everything needed to show one shot again: the world as it was when it was
+/// fired, where the projectiles were on every tick after that, and the
+/// craters and damage along the way.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ShotReplay {
+    shooter: usize,
+    wind: f32,
+    terrain: Terrain,
+    tanks: Vec<TankFrame>,
+    /// Where every projectile in the air was on each tick, starting from the
+    /// moment the shot was fired.
+    frames: Vec<Vec<[f32; 2]>>,
+    events: Vec<TimedEvent>,
+}
+
+impl ShotReplay {
+    /// Starts recording a shot `shooter` just fired, with `projectiles` on
+    /// their way out of the turret.
+    pub fn new(
+        shooter: PlayerId,
+        wind: f32,
+        terrain: &Terrain,
+        tanks: &[Tank],
+        projectiles: &[Projectile],
+    ) -> Self {
+        let mut shot = ShotReplay {
+            shooter: shooter.0,
+            wind,
+            terrain: terrain.clone(),
+            tanks: tanks
+                .iter()
+                .map(|tank| TankFrame {
+                    x: tank.pos.x,
+                    angle: tank.angle,
+                    color: tank.color,
+                    health: tank.health,
+                })
+                .collect(),
+            frames: Vec::new(),
+            events: Vec::new(),
+        };
+        shot.record(projectiles);
+        shot
+    }
+
+    /// Records where `projectiles` are at the end of a tick.
+    pub fn record(&mut self, projectiles: &[Projectile]) {
+        self.frames.push(
+            projectiles
+                .iter()
+                .map(|projectile| projectile.pos.to_array())
+                .collect(),
+        );
+    }
+
+    /// Records a blast. `scorch_radius` is `None` for blasts that don't scorch.
+    pub fn explosion(&mut self, pos: Vec2, crater_radius: f32, scorch_radius: Option<f32>) {
+        self.event(ReplayEvent::Explosion {
+            pos: pos.to_array(),
+            crater_radius,
+            scorch_radius,
+        });
+    }
+
+    /// Records `player`'s tank taking `amount` damage.
+    pub fn damage(&mut self, player: PlayerId, amount: i32) {
+        self.event(ReplayEvent::Damage {
+            player: player.0,
+            amount,
+        });
+    }
+
+    /// Checks that the shot could have been recorded by the game, and lasts no
+    /// more than `max_ticks` ticks.
+    fn validate(&self, max_ticks: usize) -> Result<(), String> {
+        let finite = |pos: &[f32; 2]| pos.iter().all(|value| value.is_finite());
+        let blast_radius = |radius: f32| (0.0..=MAX_BLAST_RADIUS).contains(&radius);
+
+        if !self.terrain.is_valid() {
+            return Err("bad terrain".to_string());
+        }
+        if !(1..=PLAYER_RANGE.1).contains(&self.tanks.len()) || self.shooter >= self.tanks.len() {
+            return Err("bad players".to_string());
+        }
+        if !self.wind.is_finite()
+            || self.tanks.iter().any(|tank| {
+                !tank.x.is_finite()
+                    || !tank.angle.is_finite()
+                    || !(0..=MAX_HEALTH).contains(&tank.health)
+            })
+        {
+            return Err("bad tanks or wind".to_string());
+        }
+        if self.frames.is_empty()
+            || self.frames.len() > max_ticks
+            || self
+                .frames
+                .iter()
+                .any(|frame| frame.len() > MAX_PROJECTILES || !frame.iter().all(finite))
+        {
+            return Err("bad projectile positions".to_string());
+        }
+
+        let events_valid = self.events.iter().all(|timed| {
+            timed.tick <= self.frames.len()
+                && match timed.event {
+                    ReplayEvent::Explosion {
+                        pos,
+                        crater_radius,
+                        scorch_radius,
+                    } => {
+                        finite(&pos)
+                            && blast_radius(crater_radius)
+                            && scorch_radius.is_none_or(blast_radius)
+                    }
+                    ReplayEvent::Damage { player, amount } => {
+                        player < self.tanks.len() && (0..=MAX_HEALTH).contains(&amount)
+                    }
+                }
+        });
+        if !events_valid {
+            return Err("bad events".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Events belong to the tick being worked out, which gets recorded once
+    /// it's done.
+    fn event(&mut self, event: ReplayEvent) {
+        self.events.push(TimedEvent {
+            tick: self.frames.len(),
+            event,
+        });
+    }
+}
+
+/// Every shot of a match, in the order they were fired. This is what gets
+/// saved to share a match.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct MatchReplay {
+    pub seed: u64,
+    /// How many ticks per second the shots were recorded at, so they play
+    /// back at the right speed whatever the settings are.
+    pub updates_per_second: u32,
+    pub shots: Vec<ShotReplay>,
+}
+
+impl MatchReplay {
+    pub fn new(seed: u64, updates_per_second: u32) -> Self {
+        MatchReplay {
+            seed,
+            updates_per_second,
+            shots: Vec::new(),
+        }
+    }
+
+    /// Writes the replay to a new file in the replays directory and returns
+    /// where it went.
+    pub fn save(&self) -> std::io::Result<PathBuf> {
+        let dir = replays_dir().ok_or_else(|| std::io::Error::other("no replays directory"))?;
+        fs::create_dir_all(&dir)?;
+
+        // The seed keeps files from different matches apart, and the shot
+        // count tells saves of the same match apart.
+        let path = dir.join(format!(
+            "replay-{:016x}-{}.json",
+            self.seed,
+            self.shots.len()
+        ));
+        let text = serde_json::to_string(self).map_err(std::io::Error::other)?;
+        fs::write(&path, text)?;
+
+        Ok(path)
+    }
+
+    /// Reads a replay file. Replay files get passed around, so anything in
+    /// one that the game could never have recorded is turned down.
+    pub fn load(path: &Path) -> Result<MatchReplay, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let replay: MatchReplay = serde_json::from_str(&text).map_err(|err| err.to_string())?;
+        replay.validate()?;
+
+        Ok(replay)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_UPDATES_PER_SECOND).contains(&self.updates_per_second) {
+            return Err(format!(
+                "bad tick rate of {} per second",
+                self.updates_per_second
+            ));
+        }
+
+        let max_ticks = (MAX_SHOT_TIME * self.updates_per_second as f32) as usize;
+        for (index, shot) in self.shots.iter().enumerate() {
+            shot.validate(max_ticks)
+                .map_err(|err| format!("shot {}: {}", index + 1, err))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Where saved replays go in the platform's data directory.
+fn replays_dir() -> Option<PathBuf> {
+    let dirs = directories::ProjectDirs::from("", "", "pt-rs")?;
+    Some(dirs.data_dir().join("replays"))
+}
+
+/// Plays recorded shots back one after the other on a copy of the world as it
+/// was, leaving the actual game untouched.
+pub struct Playback {
+    shots: Vec<ShotReplay>,
+    /// Index into `shots` of the one playing.
+    shot: usize,
+    /// Ticks of the current shot played so far. Slowed down playback moves
+    /// less than a tick per update, hence the fraction.
+    tick: f32,
+    /// Recorded ticks per second, used to time the pause after each shot.
+    recorded_ups: f32,
+    /// Recorded ticks that make up one update at normal speed.
+    ticks_per_update: f32,
+    /// How many times faster than it was recorded the replay is playing.
+    speed: f32,
+    /// Index into the current shot's events of the next one to happen.
+    next_event: usize,
+    terrain: Terrain,
+    terrain_mesh: graphics::Mesh,
+    tanks: Vec<Tank>,
+    /// Damage each tank has taken so far in the current shot.
+    damage: Vec<i32>,
+}
+
+impl Playback {
+    /// Sets up playing `shots`, recorded at `recorded_ups` ticks per second,
+    /// for a game updating `updates_per_second` times per second. Returns
+    /// `None` if there's nothing to play.
+    pub fn new(
+        ctx: &mut Context,
+        shots: &[ShotReplay],
+        recorded_ups: u32,
+        updates_per_second: u32,
+    ) -> GameResult<Option<Self>> {
+        let Some(first) = shots.first() else {
+            return Ok(None);
+        };
+
+        let mut playback = Playback {
+            shots: shots.to_vec(),
+            shot: 0,
+            tick: 0.0,
+            recorded_ups: recorded_ups.max(1) as f32,
+            ticks_per_update: recorded_ups.max(1) as f32 / updates_per_second.max(1) as f32,
+            speed: 1.0,
+            next_event: 0,
+            terrain_mesh: first.terrain.build_mesh(ctx)?,
+            terrain: first.terrain.clone(),
+            tanks: Vec::new(),
+            damage: Vec::new(),
+        };
+        playback.load_shot(ctx)?;
+
+        Ok(Some(playback))
+    }
+
+    /// Puts the world back the way it was when the current shot was fired.
+    fn load_shot(&mut self, ctx: &mut Context) -> GameResult {
+        let shot = &self.shots[self.shot];
+        self.terrain = shot.terrain.clone();
+        self.terrain_mesh = self.terrain.build_mesh(ctx)?;
+        self.tanks = shot
+            .tanks
+            .iter()
+            .map(|frame| {
+                let mut tank = Tank::new(frame.x, frame.color);
+                tank.angle = frame.angle;
+                tank.health = frame.health;
+                tank.rest_on(&self.terrain);
+                tank
+            })
+            .collect();
+        self.damage = vec![0; self.tanks.len()];
+        self.tick = 0.0;
+        self.next_event = 0;
+
+        Ok(())
+    }
+
+    /// Doubles the playback speed, up to the fastest there is.
+    pub fn faster(&mut self) {
+        self.speed = (self.speed * 2.0).min(SPEED_RANGE.1);
+    }
+
+    /// Halves the playback speed, down to the slowest there is.
+    pub fn slower(&mut self) {
+        self.speed = (self.speed / 2.0).max(SPEED_RANGE.0);
+    }
+
+    /// Moves the replay along by one update, replaying every event it passes
+    /// with explosions going off in `particles`. Returns whether there's still
+    /// more to play.
+    pub fn update(&mut self, ctx: &mut Context, particles: &mut Particles) -> GameResult<bool> {
+        self.tick += self.speed * self.ticks_per_update;
+
+        let shot = &self.shots[self.shot];
+        let mut terrain_changed = false;
+        while let Some(timed) = shot
+            .events
+            .get(self.next_event)
+            .filter(|timed| timed.tick as f32 <= self.tick)
+        {
+            // The same changes the game made, in the same order.
+            match timed.event {
+                ReplayEvent::Explosion {
+                    pos,
+                    crater_radius,
+                    scorch_radius,
+                } => {
+                    let pos = Vec2::from(pos);
+                    self.terrain.deform(pos, crater_radius);
+                    particles.explode(pos, crater_radius);
+                    if let Some(radius) = scorch_radius {
+                        self.terrain.scorch(pos, radius);
+                    }
+                    for tank in &mut self.tanks {
+                        tank.rest_on(&self.terrain);
+                    }
+                    terrain_changed = true;
+                }
+                ReplayEvent::Damage { player, amount } => {
+                    if let Some(tank) = self.tanks.get_mut(player) {
+                        tank.health = tank.health.saturating_sub(amount);
+                        self.damage[player] = self.damage[player].saturating_add(amount);
+                    }
+                }
+            }
+            self.next_event += 1;
+        }
+
+        if self.tick >= shot.frames.len() as f32 + END_PAUSE * self.recorded_ups {
+            self.shot += 1;
+            if self.shot == self.shots.len() {
+                return Ok(false);
+            }
+            self.load_shot(ctx)?;
+        } else if terrain_changed {
+            self.terrain_mesh = self.terrain.build_mesh(ctx)?;
+        }
+
+        Ok(true)
+    }
+
+    pub fn draw_layer(&self, canvas: &mut Canvas, layer: Layer) {
+        let shot = &self.shots[self.shot];
+        let frame = (self.tick as usize).min(shot.frames.len().saturating_sub(1));
+
+        match layer {
+            Layer::Terrain => canvas.draw(&self.terrain_mesh, graphics::DrawParam::new()),
+            Layer::Tanks => {
+                for tank in &self.tanks {
+                    tank.draw(canvas);
+                }
+            }
+            // The whole path so far stays on screen, so the shot can be
+            // followed from the turret to wherever it ended up.
+            Layer::Projectiles => {
+                for &pos in shot.frames[..frame].iter().flatten() {
+                    projectile::draw_trail_dot(canvas, Vec2::from(pos), 0.3);
+                }
+                for &pos in shot.frames.get(frame).into_iter().flatten() {
+                    projectile::draw_shell(canvas, Vec2::from(pos));
+                }
+            }
+            // Explosions are shown by the game's own particles.
+            Layer::Effects => {}
+            Layer::Hud => {
+                for (tank, &damage) in self.tanks.iter().zip(&self.damage) {
+                    if damage > 0 {
+                        draw_text(
+                            canvas,
+                            &format!("-{}", damage),
+                            16.0,
+                            Vec2::new(tank.pos.x, tank.body().top() - 6.0),
+                            Vec2::new(0.5, 1.0),
+                        );
+                    }
+                }
+            }
+            Layer::Overlay => {
+                let arrow = if shot.wind < 0.0 { "<" } else { ">" };
+                draw_text(
+                    canvas,
+                    &format!(
+                        "Replay: {}'s shot ({}/{})\nWind: {:.0} {}  Speed: x{}\nUp/Down: speed  Escape: stop",
+                        PlayerId(shot.shooter),
+                        self.shot + 1,
+                        self.shots.len(),
+                        shot.wind.abs(),
+                        arrow,
+                        self.speed
+                    ),
+                    16.0,
+                    Vec2::new(self.terrain.size().x / 2.0, 8.0),
+                    Vec2::new(0.5, 0.0),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weapons::Weapon;
+
+    /// A one-shot match as the game would record it.
+    fn recorded_match() -> MatchReplay {
+        let terrain = Terrain::flat((48, 48), 10);
+        let tanks = [Tank::new(45.0, Color::RED), Tank::new(435.0, Color::BLUE)];
+        let projectiles = [Projectile::new(
+            Vec2::new(45.0, 360.0),
+            Vec2::new(100.0, -100.0),
+            PlayerId(0),
+            Weapon::Shell,
+        )];
+
+        let mut shot = ShotReplay::new(PlayerId(0), 10.0, &terrain, &tanks, &projectiles);
+        shot.record(&projectiles);
+        shot.explosion(Vec2::new(100.0, 380.0), 25.0, Some(40.0));
+        shot.damage(PlayerId(1), 20);
+        shot.record(&[]);
+
+        let mut replay = MatchReplay::new(1, 60);
+        replay.shots.push(shot);
+        replay
+    }
+
+    /// `replay` as it would be loaded from a file, after `tamper` has edited it.
+    fn reload(replay: &MatchReplay, tamper: impl FnOnce(&mut serde_json::Value)) -> bool {
+        let mut json = serde_json::to_value(replay).unwrap();
+        tamper(&mut json);
+        serde_json::from_value::<MatchReplay>(json)
+            .map_err(|err| err.to_string())
+            .and_then(|replay| replay.validate())
+            .is_ok()
+    }
+
+    #[test]
+    fn recorded_replays_load() {
+        assert!(reload(&recorded_match(), |_| {}));
+    }
+
+    #[test]
+    fn broken_replay_files_are_turned_down() {
+        let replay = recorded_match();
+        let shot = "/shots/0";
+        let edits: [(&str, serde_json::Value); 5] = [
+            ("/terrain/scorch", serde_json::json!([0.0])),
+            ("/shooter", serde_json::json!(5)),
+            ("/frames", serde_json::json!([])),
+            ("/events/0/tick", serde_json::json!(100)),
+            ("/events/1/event/Damage/player", serde_json::json!(7)),
+        ];
+        for (pointer, value) in edits {
+            let pointer = format!("{}{}", shot, pointer);
+            assert!(
+                !reload(&replay, |json| *json.pointer_mut(&pointer).unwrap() = value),
+                "{}",
+                pointer
+            );
+        }
+
+        assert!(!reload(&replay, |json| json["updates_per_second"] = 0.into()));
+    }
+}
@@ -0,0 +1,64 @@
+use crate::{turn::PlayerId, Tank};
+
+/// Keeps score over a match made up of several rounds. Each round is played on
+/// fresh terrain with fresh tanks, so the points the tanks earn are banked here
+/// when it ends.
+#[derive(PartialEq, Debug)]
+pub struct MatchState {
+    /// How many rounds the match lasts.
+    pub rounds: usize,
+    /// How many rounds have been finished so far.
+    pub rounds_played: usize,
+    /// Points banked by each player in the rounds finished so far.
+    pub scores: Vec<i32>,
+}
+
+impl MatchState {
+    pub fn new(rounds: usize, players: usize) -> Self {
+        MatchState {
+            rounds,
+            rounds_played: 0,
+            scores: vec![0; players],
+        }
+    }
+
+    /// The round being played, or the last one played once the match is over.
+    /// Counts from 1.
+    pub fn round(&self) -> usize {
+        (self.rounds_played + 1).min(self.rounds)
+    }
+
+    /// A player's total so far, including whatever their tank has earned in
+    /// the round still being played.
+    pub fn score(&self, player: PlayerId, tank: &Tank) -> i32 {
+        self.scores[player.0] + tank.score
+    }
+
+    /// Banks the points every tank earned this round.
+    pub fn finish_round(&mut self, tanks: &mut [Tank]) {
+        for (score, tank) in self.scores.iter_mut().zip(tanks) {
+            *score += std::mem::take(&mut tank.score);
+        }
+        self.rounds_played += 1;
+    }
+
+    /// Whether every round of the match has been played.
+    pub fn is_over(&self) -> bool {
+        self.rounds_played >= self.rounds
+    }
+
+    /// The player with the most points, or `None` if the lead is shared.
+    pub fn leader(&self) -> Option<PlayerId> {
+        let best = *self.scores.iter().max()?;
+        let mut leaders = self
+            .scores
+            .iter()
+            .enumerate()
+            .filter(|(_, &score)| score == best);
+
+        match (leaders.next(), leaders.next()) {
+            (Some((index, _)), None) => Some(PlayerId(index)),
+            _ => None,
+        }
+    }
+}
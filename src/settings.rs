@@ -0,0 +1,288 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ggez::input::keyboard::KeyCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{Direction, SCREEN_SIZE};
+
+/// Name of the settings file, looked for next to the executable and then in
+/// the platform's config directory.
+const SETTINGS_FILE: &str = "pt-rs.toml";
+
+/// Weakest gravity allowed, in pixels per second squared. Without enough of
+/// it, a shot fired straight up would take forever to come back down.
+const MIN_GRAVITY: f32 = 50.0;
+
+/// Smallest window allowed, in pixels. Anything smaller leaves no room for the
+/// tanks and the HUD.
+const MIN_WINDOW_SIZE: (f32, f32) = (160.0, 160.0);
+
+/// Fastest the game can be set to tick, in updates per second. Replays
+/// recorded faster than this can't have come from the game.
+pub const MAX_UPDATES_PER_SECOND: u32 = 1000;
+
+/// Everything about the game that can be changed from `pt-rs.toml`. Anything
+/// missing from the file keeps its default.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(default)]
+pub struct Settings {
+    /// How many times per second the game world is simulated.
+    pub updates_per_second: u32,
+    /// Downward acceleration applied to projectiles, in pixels per second squared.
+    pub gravity: f32,
+    /// Strongest the wind can blow either way, as a horizontal acceleration in
+    /// pixels per second squared.
+    pub max_wind: f32,
+    /// How fast a tank drives, in pixels per second.
+    pub tank_speed: f32,
+    /// How many rounds make up a match.
+    pub rounds: usize,
+    pub window: WindowSettings,
+    pub keys: KeyBindings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            // Often enough for tanks to drive smoothly rather than hop along.
+            updates_per_second: 60,
+            gravity: 200.0,
+            max_wind: 60.0,
+            tank_speed: 30.0,
+            rounds: 3,
+            window: WindowSettings::default(),
+            keys: KeyBindings::default(),
+        }
+    }
+}
+
+/// Size and mode of the game window. The battlefield fills the window, so a
+/// bigger window means a bigger battlefield. In fullscreen the window takes the
+/// size of the desktop instead.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(default)]
+pub struct WindowSettings {
+    pub width: f32,
+    pub height: f32,
+    pub fullscreen: bool,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            width: SCREEN_SIZE.0 as f32,
+            height: SCREEN_SIZE.1 as f32,
+            fullscreen: false,
+        }
+    }
+}
+
+/// Something the player whose turn it is can do with a key.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Action {
+    AimLeft,
+    AimRight,
+    PowerUp,
+    PowerDown,
+    PowerUpFast,
+    PowerDownFast,
+    Drive(Direction),
+    NextWeapon,
+    /// Picks the weapon at this index in `Weapon::ARSENAL`.
+    SelectWeapon(usize),
+    Fire,
+}
+
+/// Which key does what during a turn.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub aim_left: KeyCode,
+    pub aim_right: KeyCode,
+    pub power_up: KeyCode,
+    pub power_down: KeyCode,
+    pub power_up_fast: KeyCode,
+    pub power_down_fast: KeyCode,
+    pub drive_left: KeyCode,
+    pub drive_right: KeyCode,
+    pub next_weapon: KeyCode,
+    /// Keys that pick each weapon in `Weapon::ARSENAL` directly, in order.
+    pub weapons: Vec<KeyCode>,
+    pub fire: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            aim_left: KeyCode::Left,
+            aim_right: KeyCode::Right,
+            power_up: KeyCode::Up,
+            power_down: KeyCode::Down,
+            power_up_fast: KeyCode::PageUp,
+            power_down_fast: KeyCode::PageDown,
+            drive_left: KeyCode::A,
+            drive_right: KeyCode::D,
+            next_weapon: KeyCode::Tab,
+            weapons: vec![KeyCode::Key1, KeyCode::Key2, KeyCode::Key3],
+            fire: KeyCode::Space,
+        }
+    }
+}
+
+impl KeyBindings {
+    /// What `key` does, if it's bound to anything. Of course, not every key
+    /// does something, so we return `None` if this is the case.
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        let bindings = [
+            (self.aim_left, Action::AimLeft),
+            (self.aim_right, Action::AimRight),
+            (self.power_up, Action::PowerUp),
+            (self.power_down, Action::PowerDown),
+            (self.power_up_fast, Action::PowerUpFast),
+            (self.power_down_fast, Action::PowerDownFast),
+            (self.drive_left, Action::Drive(Direction::Left)),
+            (self.drive_right, Action::Drive(Direction::Right)),
+            (self.next_weapon, Action::NextWeapon),
+            (self.fire, Action::Fire),
+        ];
+
+        bindings
+            .into_iter()
+            .find(|&(bound, _)| bound == key)
+            .map(|(_, action)| action)
+            .or_else(|| {
+                let index = self.weapons.iter().position(|&bound| bound == key)?;
+                Some(Action::SelectWeapon(index))
+            })
+    }
+
+    /// The direction `key` drives the tank in, if it's one of the drive keys.
+    pub fn direction(&self, key: KeyCode) -> Option<Direction> {
+        match self.action(key) {
+            Some(Action::Drive(direction)) => Some(direction),
+            _ => None,
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings file next to the executable, or failing that the one
+    /// in the config directory. If there is neither, the defaults are written
+    /// to the config directory for next time. A file that can't be read is
+    /// reported and ignored rather than overwritten.
+    pub fn load() -> Settings {
+        let beside_exe = std::env::current_exe()
+            .ok()
+            .and_then(|exe| Some(exe.parent()?.join(SETTINGS_FILE)));
+        let in_config_dir = config_path();
+
+        let Some(path) = [beside_exe, in_config_dir.clone()]
+            .into_iter()
+            .flatten()
+            .find(|path| path.exists())
+        else {
+            let settings = Settings::default();
+            if let Some(path) = in_config_dir {
+                if let Err(err) = settings.save(&path) {
+                    println!("Could not write {}: {}", path.display(), err);
+                }
+            }
+            return settings;
+        };
+
+        let settings = fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| toml::from_str::<Settings>(&text).map_err(|err| err.to_string()));
+        match settings {
+            Ok(settings) => settings.sanitized(),
+            Err(err) => {
+                println!("Could not load {}, using defaults: {}", path.display(), err);
+                Settings::default()
+            }
+        }
+    }
+
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = toml::to_string_pretty(self).map_err(std::io::Error::other)?;
+        fs::write(path, text)
+    }
+
+    /// Pulls values that would break the game back into range. Values that
+    /// make no sense at all, like a window with no width, fall back to the
+    /// defaults.
+    fn sanitized(mut self) -> Settings {
+        let defaults = Settings::default();
+        let positive_or = |value: f32, default: f32| {
+            if value.is_finite() && value > 0.0 {
+                value
+            } else {
+                default
+            }
+        };
+
+        self.updates_per_second = self.updates_per_second.clamp(1, MAX_UPDATES_PER_SECOND);
+        self.rounds = self.rounds.max(1);
+        self.gravity = positive_or(self.gravity, defaults.gravity).max(MIN_GRAVITY);
+        self.tank_speed = self.tank_speed.max(0.0);
+        self.max_wind = if self.max_wind.is_finite() {
+            self.max_wind.abs()
+        } else {
+            defaults.max_wind
+        };
+        self.window.width =
+            positive_or(self.window.width, defaults.window.width).max(MIN_WINDOW_SIZE.0);
+        self.window.height =
+            positive_or(self.window.height, defaults.window.height).max(MIN_WINDOW_SIZE.1);
+        self
+    }
+}
+
+/// Where the settings file lives in the platform's config directory.
+fn config_path() -> Option<PathBuf> {
+    let dirs = directories::ProjectDirs::from("", "", "pt-rs")?;
+    Some(dirs.config_dir().join(SETTINGS_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitized_keeps_shots_coming_down() {
+        for gravity in [0.0, -200.0, f32::NAN] {
+            let settings = Settings {
+                gravity,
+                ..Settings::default()
+            }
+            .sanitized();
+            assert!(settings.gravity >= MIN_GRAVITY);
+        }
+    }
+
+    #[test]
+    fn sanitized_rejects_empty_windows() {
+        let mut settings = Settings::default();
+        settings.window.width = 0.0;
+        settings.window.height = -480.0;
+        let settings = settings.sanitized();
+        assert_eq!(settings.window, WindowSettings::default());
+    }
+
+    #[test]
+    fn sanitized_keeps_the_tick_rate_replays_can_load() {
+        for (updates_per_second, expected) in [(0, 1), (60, 60), (1200, MAX_UPDATES_PER_SECOND)] {
+            let settings = Settings {
+                updates_per_second,
+                ..Settings::default()
+            }
+            .sanitized();
+            assert_eq!(settings.updates_per_second, expected);
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use ggez::{
+    glam::*,
+    graphics::{self, Color},
+    Context, GameResult,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::GRID_CELL_SIZE;
+
+/// Range of heights the generated terrain stays within, as fractions of the
+/// height of the battlefield.
+const TERRAIN_HEIGHT_RANGE: (f32, f32) = (1.0 / 8.0, 1.0 / 2.0);
+
+/// How much the spread of the random displacement shrinks by each time the
+/// segments are halved. Lower values give smoother hills.
+const ROUGHNESS: f32 = 0.55;
+
+/// How much scorching each column of terrain loses at the end of every turn.
+const SCORCH_FADE: f32 = 0.25;
+
+/// The ground tanks drive on and projectiles crash into, stored as one height per
+/// grid column.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Terrain {
+    /// Height of the ground in each column, in cells above the bottom of the screen.
+    heights: Vec<isize>,
+    /// How burnt the surface of each column is, from 0 (untouched) to 1 (black).
+    scorch: Vec<f32>,
+    /// Height of the battlefield in grid cells.
+    rows: isize,
+}
+
+impl Terrain {
+    /// Generates hills using midpoint displacement for a battlefield of
+    /// `columns` by `rows` grid cells. Bigger battlefields get more cells
+    /// rather than stretched ones.
+    pub fn generate(rng: &mut oorandom::Rand32, (columns, rows): (isize, isize)) -> Self {
+        let (min, max) = (
+            (rows as f32 * TERRAIN_HEIGHT_RANGE.0).round(),
+            (rows as f32 * TERRAIN_HEIGHT_RANGE.1).round(),
+        );
+
+        // Midpoint displacement works on 2^n + 1 points, so generate enough of
+        // them to cover every column and drop the ones past the edge.
+        let points = (columns.max(2) as usize - 1).next_power_of_two() + 1;
+        let mut heights = vec![0.0; points];
+        heights[0] = min + rng.rand_float() * (max - min);
+        heights[points - 1] = min + rng.rand_float() * (max - min);
+
+        // Push the midpoint of every segment up or down by a random amount,
+        // then do the same for each half with a smaller spread, until every
+        // point has been set.
+        let mut step = points - 1;
+        let mut spread = (max - min) / 2.0;
+        while step > 1 {
+            let half = step / 2;
+            for start in (0..points - 1).step_by(step) {
+                let midpoint = (heights[start] + heights[start + step]) / 2.0;
+                heights[start + half] = midpoint + (rng.rand_float() * 2.0 - 1.0) * spread;
+            }
+            step = half;
+            spread *= ROUGHNESS;
+        }
+
+        let heights = heights
+            .iter()
+            .take(columns as usize)
+            .map(|height| (height.round() as isize).clamp(min as isize, max as isize))
+            .collect();
+
+        Terrain {
+            heights,
+            scorch: vec![0.0; columns as usize],
+            rows,
+        }
+    }
+
+    /// Perfectly flat ground `height` cells high across a battlefield of
+    /// `columns` by `rows` grid cells.
+    #[cfg(test)]
+    pub fn flat((columns, rows): (isize, isize), height: isize) -> Self {
+        Terrain {
+            heights: vec![height; columns as usize],
+            scorch: vec![0.0; columns as usize],
+            rows,
+        }
+    }
+
+    /// Whether the terrain holds together: a scorch value for every column, and
+    /// every column within the battlefield. Only terrain read from a file can
+    /// be anything else.
+    pub fn is_valid(&self) -> bool {
+        self.rows > 0
+            && !self.heights.is_empty()
+            && self.scorch.len() == self.heights.len()
+            && self
+                .heights
+                .iter()
+                .all(|height| (0..=self.rows).contains(height))
+            && self
+                .scorch
+                .iter()
+                .all(|scorch| (0.0..=1.0).contains(scorch))
+    }
+
+    /// Number of columns the terrain spans.
+    pub fn width(&self) -> isize {
+        self.heights.len() as isize
+    }
+
+    /// Size of the battlefield the terrain spans, in pixels.
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(
+            (self.width() * GRID_CELL_SIZE) as f32,
+            (self.rows * GRID_CELL_SIZE) as f32,
+        )
+    }
+
+    /// Height of the ground in column `x`. Columns outside the screen have no ground.
+    pub fn height_at(&self, x: isize) -> isize {
+        usize::try_from(x)
+            .ok()
+            .and_then(|x| self.heights.get(x))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Grid row of the topmost ground cell in column `x`.
+    pub fn surface_y(&self, x: isize) -> isize {
+        self.rows - self.height_at(x)
+    }
+
+    /// Pixel y of the ground's surface at the pixel column `x`, smoothed out by
+    /// blending between the tops of the two nearest columns. Past the edges of
+    /// the screen the ground carries on at the height of the last column.
+    pub fn surface_at(&self, x: f32) -> f32 {
+        let column = x / GRID_CELL_SIZE as f32 - 0.5;
+        let (left, blend) = (column.floor(), column - column.floor());
+        let top = |x: isize| (self.surface_y(x.clamp(0, self.width() - 1)) * GRID_CELL_SIZE) as f32;
+
+        top(left as isize) * (1.0 - blend) + top(left as isize + 1) * blend
+    }
+
+    /// Whether the pixel-space point `pos` lies inside the ground.
+    pub fn is_solid(&self, pos: Vec2) -> bool {
+        let column = (pos.x / GRID_CELL_SIZE as f32).floor() as isize;
+        pos.y >= (self.surface_y(column) * GRID_CELL_SIZE) as f32
+    }
+
+    /// Carves a circular crater of `radius` pixels around the pixel-space point
+    /// `center`. Any ground above the bottom of the circle goes with it, since
+    /// the terrain can't have overhangs.
+    pub fn deform(&mut self, center: Vec2, radius: f32) {
+        for (x, height) in self.heights.iter_mut().enumerate() {
+            let column = (x as f32 + 0.5) * GRID_CELL_SIZE as f32;
+            let dx = column - center.x;
+            if dx.abs() >= radius {
+                continue;
+            }
+
+            let bottom = center.y + (radius * radius - dx * dx).sqrt();
+            let bottom_row = (bottom / GRID_CELL_SIZE as f32).round() as isize;
+            *height = (*height).min(self.rows - bottom_row).max(0);
+        }
+    }
+
+    /// Burns the surface around the pixel-space point `pos`. Columns right
+    /// underneath get the most scorching, falling off to none at `radius` pixels.
+    pub fn scorch(&mut self, pos: Vec2, radius: f32) {
+        for (x, scorch) in self.scorch.iter_mut().enumerate() {
+            let center = (x as f32 + 0.5) * GRID_CELL_SIZE as f32;
+            let distance = (center - pos.x).abs();
+            if distance < radius {
+                *scorch = (*scorch + 1.0 - distance / radius).min(1.0);
+            }
+        }
+    }
+
+    /// Lets scorch marks fade a little. Called once per turn.
+    pub fn fade_scorch(&mut self) {
+        for scorch in &mut self.scorch {
+            *scorch = (*scorch - SCORCH_FADE).max(0.0);
+        }
+    }
+
+    /// Builds the mesh used to draw the terrain: one filled rectangle per column,
+    /// with its surface cell darkened by however scorched it is.
+    pub fn build_mesh(&self, ctx: &mut Context) -> GameResult<graphics::Mesh> {
+        let mut builder = graphics::MeshBuilder::new();
+
+        for x in 0..self.width() {
+            let height = self.height_at(x);
+            if height == 0 {
+                continue;
+            }
+
+            builder.rectangle(
+                graphics::DrawMode::fill(),
+                graphics::Rect::new_i32(
+                    (x * GRID_CELL_SIZE) as i32,
+                    (self.surface_y(x) * GRID_CELL_SIZE) as i32,
+                    GRID_CELL_SIZE as i32,
+                    (height * GRID_CELL_SIZE) as i32,
+                ),
+                Color::from_rgb(70, 140, 50),
+            )?;
+
+            let scorch = self.scorch.get(x as usize).copied().unwrap_or(0.0);
+            if scorch > 0.0 {
+                // Fade from the grass color towards a burnt brown.
+                let surface = Color::new(
+                    0.27 - 0.12 * scorch,
+                    0.55 - 0.45 * scorch,
+                    0.2 - 0.15 * scorch,
+                    1.0,
+                );
+                builder.rectangle(
+                    graphics::DrawMode::fill(),
+                    graphics::Rect::new_i32(
+                        (x * GRID_CELL_SIZE) as i32,
+                        (self.surface_y(x) * GRID_CELL_SIZE) as i32,
+                        GRID_CELL_SIZE as i32,
+                        GRID_CELL_SIZE as i32,
+                    ),
+                    surface,
+                )?;
+            }
+        }
+
+        Ok(graphics::Mesh::from_data(ctx, builder.build()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{projectile::Projectile, turn::PlayerId, weapons::Weapon};
+
+    #[test]
+    fn flat_terrain_has_the_same_height_everywhere() {
+        let terrain = Terrain::flat((48, 48), 10);
+        for x in 0..terrain.width() {
+            assert_eq!(terrain.height_at(x), 10);
+            assert_eq!(terrain.surface_y(x), 38);
+        }
+    }
+
+    #[test]
+    fn hits_scorch_nearby_columns_and_it_fades_over_turns() {
+        let mut terrain = Terrain::flat((48, 48), 10);
+        terrain.scorch(Vec2::new(245.0, 380.0), 30.0);
+
+        // Right underneath burns the most, and columns out of reach not at all.
+        assert_eq!(terrain.scorch[24], 1.0);
+        assert!(terrain.scorch[23] > 0.0 && terrain.scorch[23] < 1.0);
+        assert!(terrain.scorch[22] < terrain.scorch[23]);
+        assert_eq!(terrain.scorch[20], 0.0);
+        assert_eq!(terrain.scorch[28], 0.0);
+
+        let before = terrain.scorch.clone();
+        terrain.fade_scorch();
+        for (after, before) in terrain.scorch.iter().zip(&before) {
+            assert_eq!(*after, (before - SCORCH_FADE).max(0.0));
+        }
+
+        for _ in 0..(1.0 / SCORCH_FADE).ceil() as usize {
+            terrain.fade_scorch();
+        }
+        assert!(terrain.scorch.iter().all(|&scorch| scorch == 0.0));
+    }
+
+    #[test]
+    fn shots_into_the_ground_hit_it() {
+        let terrain = Terrain::flat((48, 48), 10);
+        let mut projectile = Projectile::new(
+            Vec2::new(240.0, 300.0),
+            Vec2::new(0.0, 200.0),
+            PlayerId(0),
+            Weapon::Shell,
+        );
+
+        let impact = (0..60)
+            .find_map(|_| projectile.update(1.0 / 60.0, 0.0, 200.0, &terrain, &[]))
+            .expect("the shot never hit the ground");
+        assert_eq!(impact.tank, None);
+        assert!(terrain.is_solid(impact.pos));
+        // It stops close to the surface rather than deep inside the ground.
+        assert!((impact.pos.y - 380.0).abs() <= 2.0, "{:?}", impact.pos);
+    }
+}
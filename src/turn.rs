@@ -0,0 +1,149 @@
+use std::fmt;
+
+use crate::{ai::Difficulty, projectile::Projectile, Tank};
+
+/// Identifies a player, and with them their tank, by where they sit in the turn
+/// order.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct PlayerId(pub usize);
+
+impl fmt::Display for PlayerId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Player {}", self.0 + 1)
+    }
+}
+
+/// Who decides what a player's tank does.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum PlayerController {
+    /// Someone at the keyboard.
+    Human,
+    /// The computer, playing at the given difficulty.
+    Computer(Difficulty),
+}
+
+impl PlayerController {
+    /// Every kind of controller, in the order the start menu cycles through them.
+    pub const ALL: [PlayerController; 3] = [
+        PlayerController::Human,
+        PlayerController::Computer(Difficulty::Easy),
+        PlayerController::Computer(Difficulty::Hard),
+    ];
+
+    pub fn name(&self) -> String {
+        match self {
+            PlayerController::Human => "Human".to_string(),
+            PlayerController::Computer(difficulty) => format!("Computer ({})", difficulty.name()),
+        }
+    }
+
+    /// The next controller in `ALL`, wrapping around at the end.
+    pub fn next(&self) -> PlayerController {
+        let index = PlayerController::ALL
+            .iter()
+            .position(|controller| controller == self)
+            .map_or(0, |index| index + 1);
+        PlayerController::ALL[index % PlayerController::ALL.len()]
+    }
+}
+
+/// Owns the tanks and keeps track of whose turn it is. Player input only ever
+/// reaches a tank through here, so only the current player's tank responds.
+#[derive(PartialEq, Debug)]
+pub struct TurnManager {
+    tanks: Vec<Tank>,
+    /// Who plays each tank, in the same order as `tanks`.
+    controllers: Vec<PlayerController>,
+    current_player: PlayerId,
+}
+
+impl TurnManager {
+    /// Starts with the first tank's player.
+    pub fn new(tanks: Vec<Tank>, controllers: Vec<PlayerController>) -> Self {
+        TurnManager {
+            tanks,
+            controllers,
+            current_player: PlayerId(0),
+        }
+    }
+
+    /// Swaps in a fresh set of tanks for a new round, played by the same
+    /// players. The first player goes first again.
+    pub fn new_round(&mut self, tanks: Vec<Tank>) {
+        self.tanks = tanks;
+        self.current_player = PlayerId(0);
+    }
+
+    pub fn tanks(&self) -> &[Tank] {
+        &self.tanks
+    }
+
+    pub fn tanks_mut(&mut self) -> &mut [Tank] {
+        &mut self.tanks
+    }
+
+    pub fn tank(&self, player: PlayerId) -> &Tank {
+        &self.tanks[player.0]
+    }
+
+    pub fn current_player(&self) -> PlayerId {
+        self.current_player
+    }
+
+    pub fn controllers(&self) -> &[PlayerController] {
+        &self.controllers
+    }
+
+    /// Who is playing the current turn.
+    pub fn current_controller(&self) -> PlayerController {
+        self.controllers[self.current_player.0]
+    }
+
+    /// The tank whose turn it is.
+    pub fn current_tank_mut(&mut self) -> &mut Tank {
+        &mut self.tanks[self.current_player.0]
+    }
+
+    /// Fires the current player's tank, using up a shot of its weapon. The tank
+    /// stops driving, since the player's turn is over once the shot is away.
+    pub fn fire(&mut self) -> Projectile {
+        let owner = self.current_player;
+        let tank = self.current_tank_mut();
+        tank.direction = None;
+
+        let projectile = Projectile::new(
+            tank.turret_tip(),
+            tank.launch_velocity(),
+            owner,
+            tank.weapon,
+        );
+        tank.use_ammo();
+
+        projectile
+    }
+
+    /// Hands control over to the next player whose tank is still standing.
+    pub fn end_turn(&mut self) {
+        for _ in 0..self.tanks.len() {
+            self.current_player = PlayerId((self.current_player.0 + 1) % self.tanks.len());
+            if self.tanks[self.current_player.0].health > 0 {
+                break;
+            }
+        }
+    }
+
+    /// The end of round check: once only one tank has any health left, its
+    /// player has won.
+    pub fn winner(&self) -> Option<PlayerId> {
+        let mut alive = self
+            .tanks
+            .iter()
+            .enumerate()
+            .filter(|(_, tank)| tank.health > 0);
+
+        match (alive.next(), alive.next()) {
+            (Some((index, _)), None) => Some(PlayerId(index)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,121 @@
+/// What happens around the point where a weapon goes off.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Blast {
+    /// Radius of the crater blown out of the ground, in pixels.
+    pub crater_radius: f32,
+    /// Damage dealt to a tank right where the blast goes off.
+    pub max_damage: i32,
+    /// Distance in pixels at which the damage has fallen off to nothing.
+    /// Damage falls off linearly from `max_damage` at a direct hit to nothing here.
+    pub damage_radius: f32,
+}
+
+impl Blast {
+    /// How much damage the blast does to a tank `distance` pixels away from it.
+    pub fn damage_at(&self, distance: f32) -> i32 {
+        if distance >= self.damage_radius {
+            return 0;
+        }
+
+        (self.max_damage as f32 * (1.0 - distance / self.damage_radius)).round() as i32
+    }
+}
+
+/// Everything a tank can fire.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Weapon {
+    /// A plain shell that explodes where it lands.
+    Shell,
+    /// Breaks up into a spread of `Bomblet`s at the top of its arc.
+    ClusterBomb,
+    /// What a `ClusterBomb` breaks up into. Can't be picked on its own.
+    Bomblet,
+    /// Blows a big hole in the ground without hurting anyone.
+    Digger,
+}
+
+impl Weapon {
+    /// The weapons players can pick from, in the order Tab cycles through them.
+    pub const ARSENAL: [Weapon; 3] = [Weapon::Shell, Weapon::ClusterBomb, Weapon::Digger];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Weapon::Shell => "Shell",
+            Weapon::ClusterBomb => "Cluster Bomb",
+            Weapon::Bomblet => "Bomblet",
+            Weapon::Digger => "Digger",
+        }
+    }
+
+    pub fn blast(&self) -> Blast {
+        match self {
+            Weapon::Shell => Blast {
+                crater_radius: 25.0,
+                max_damage: 40,
+                damage_radius: 40.0,
+            },
+            Weapon::ClusterBomb => Blast {
+                crater_radius: 15.0,
+                max_damage: 20,
+                damage_radius: 25.0,
+            },
+            Weapon::Bomblet => Blast {
+                crater_radius: 12.0,
+                max_damage: 15,
+                damage_radius: 20.0,
+            },
+            Weapon::Digger => Blast {
+                crater_radius: 45.0,
+                max_damage: 0,
+                damage_radius: 0.0,
+            },
+        }
+    }
+
+    /// How many shots of this weapon a tank starts each round with, or `None`
+    /// if it never runs out.
+    pub fn starting_ammo(&self) -> Option<u32> {
+        match self {
+            Weapon::ClusterBomb => Some(3),
+            Weapon::Digger => Some(2),
+            Weapon::Shell | Weapon::Bomblet => None,
+        }
+    }
+
+    /// The weapon this one splits into at the top of its arc and how many of
+    /// them, if it splits at all.
+    pub fn fragments(&self) -> Option<(Weapon, usize)> {
+        match self {
+            Weapon::ClusterBomb => Some((Weapon::Bomblet, 5)),
+            _ => None,
+        }
+    }
+
+    /// The next weapon in `ARSENAL`, wrapping around at the end.
+    pub fn next(&self) -> Weapon {
+        let index = Weapon::ARSENAL
+            .iter()
+            .position(|weapon| weapon == self)
+            .map_or(0, |index| index + 1);
+        Weapon::ARSENAL[index % Weapon::ARSENAL.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn damage_falls_off_with_distance() {
+        let blast = Weapon::Shell.blast();
+
+        // A direct hit does full damage, a near miss some, and a far shot none.
+        assert_eq!(blast.damage_at(0.0), blast.max_damage);
+        assert_eq!(
+            blast.damage_at(blast.damage_radius / 2.0),
+            blast.max_damage / 2
+        );
+        assert_eq!(blast.damage_at(blast.damage_radius), 0);
+        assert_eq!(blast.damage_at(blast.damage_radius * 2.0), 0);
+    }
+}